@@ -0,0 +1,294 @@
+use std::sync::OnceLock;
+
+use crate::constants::*;
+use crate::types::{Bitboard, Color, PieceType, Square};
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_STEPS: [(i8, i8); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+// Found once with a seeded sparse random search, an entry is valid when no two
+// occupancies with different attack sets hash to the same index
+const BISHOP_MAGICS: [u64; 64] = [
+    0x4050109009052160, 0x010a06d809010018, 0x0010014041002828, 0x0808208428020008,
+    0x0001104000111110, 0x04010108c0000680, 0x6000440420080020, 0x8000104802082040,
+    0x0c04081010420064, 0x0812025218020684, 0x4001100100411208, 0x80000404028b0000,
+    0x0020021210021908, 0xd5400a0910082000, 0x1004840082082022, 0x0010024064100808,
+    0x8090000a10702080, 0x000402200800a90d, 0x0840400800812182, 0x2002062020204202,
+    0x800c045202110100, 0x0200802808210802, 0x004e0004010108c2, 0x040188010090b000,
+    0x0204504004701010, 0x8008020008100121, 0x4280480810108010, 0x1018080200e20020,
+    0x1041001001004000, 0x0010002081008800, 0x0051010044040180, 0x0010861018220200,
+    0x0001200800101000, 0x4038b00400080808, 0x4000280800010200, 0xc09e020080080080,
+    0x0840008020020020, 0x00300100400a0040, 0x010421020e040080, 0x441210822202060a,
+    0x1002100220600a40, 0x124a051120090824, 0x0002201410000203, 0x080010a018000100,
+    0x8080080104000040, 0x02c0008810800040, 0x0010014800801100, 0x0021014600881200,
+    0x000100a804400000, 0x8011220a100c8010, 0x0002084212d00004, 0x0800200108480440,
+    0x0080245002120000, 0x8060400881410424, 0x00100408008c1001, 0x0004210409020a01,
+    0x0000202128201000, 0x0000220841080800, 0x080002004a080400, 0x2002004171048804,
+    0x0142000050020880, 0x000044a102222200, 0x9020050404080200, 0x0440240ea4004080,
+];
+
+const ROOK_MAGICS: [u64; 64] = [
+    0x0180022040081080, 0x0440022000100040, 0x0200102082000840, 0x29001001002048c4,
+    0x0600020044100820, 0x1500090028140006, 0x4480020000802100, 0x020006002100884c,
+    0x0006002880410200, 0x5400808020004000, 0x0011001242200100, 0x0000801000080084,
+    0x0020808004000800, 0x00a1000300080400, 0x3009000401002200, 0x00060002008c0841,
+    0x04c0008000402084, 0x2120004000300044, 0x0000110020010043, 0x0000818010004800,
+    0x0400310014580100, 0x10080801044010a0, 0x02a0040008029001, 0x8010820001c09704,
+    0x0020400080008024, 0x0560002080400080, 0x0000100080200081, 0x0000100100200900,
+    0x8000080080040080, 0x2012000200041008, 0x0080028400100108, 0x0084008200010854,
+    0x4040400020800080, 0x4000200048401000, 0x1010002801200400, 0x0208200901001000,
+    0x9030810401800801, 0x0406201048010440, 0x4203000401010200, 0x0000008402000041,
+    0x0040004220818000, 0x0020201000404001, 0x0020001008004040, 0x0001040810010020,
+    0x0004000800048080, 0x004c008002008004, 0x4008010002008080, 0x8404204081020004,
+    0x0040045424800080, 0x4040004080200080, 0x8282a00100481300, 0x0900801000080080,
+    0xa113001088000700, 0x8002008004000280, 0x600018d201100400, 0x04008408a5004600,
+    0x0802142081024202, 0x008094430200a082, 0x100910820021410a, 0x0400500020450109,
+    0x0202002008100402, 0x4821000812040003, 0x0084281002209104, 0x8001000140882205,
+];
+
+// Per-square lookup data for one slider, the occupancy is reduced to the
+// relevant mask and hashed (or PEXT-ed) into this square's slice of the table
+#[derive(Copy, Clone)]
+#[cfg_attr(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"), allow(dead_code))]
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    #[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+    fn index(&self, occupancy: &Bitboard) -> usize {
+        // Safe as the bmi2 target feature is enabled for the whole build
+        self.offset + unsafe { std::arch::x86_64::_pext_u64(occupancy.0, self.mask.0) } as usize
+    }
+
+    #[cfg(not(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")))]
+    fn index(&self, occupancy: &Bitboard) -> usize {
+        self.offset + ((occupancy.0 & self.mask.0).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+const EMPTY_MAGIC: Magic = Magic {
+    mask: Bitboard(0),
+    magic: 0,
+    shift: 0,
+    offset: 0,
+};
+
+struct AttackTables {
+    pawn: [[Bitboard; 64]; 2],
+    knight: [Bitboard; 64],
+    king: [Bitboard; 64],
+    bishop_magics: [Magic; 64],
+    rook_magics: [Magic; 64],
+    slider_attacks: Vec<Bitboard>,
+    between: [[Bitboard; 64]; 64],
+    line: [[Bitboard; 64]; 64],
+}
+
+static TABLES: OnceLock<AttackTables> = OnceLock::new();
+
+fn offset_square(sq: &Square, df: i8, dr: i8) -> Option<Square> {
+    let file = sq.file().0 as i8 + df;
+    let rank = sq.rank().0 as i8 + dr;
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some(Square((file + (rank << 3)) as u8))
+    } else {
+        None
+    }
+}
+
+fn step_attacks(sq: &Square, steps: &[(i8, i8)]) -> Bitboard {
+    let mut attacks = Bitboard(0);
+    for (df, dr) in steps.iter() {
+        if let Some(to_sq) = offset_square(sq, *df, *dr) {
+            attacks |= Bitboard::from(&to_sq);
+        }
+    }
+    attacks
+}
+
+// Ray walk used to fill the tables, far too slow to be used during search
+fn slider_attacks(sq: &Square, occupancy: &Bitboard, directions: &[(i8, i8)]) -> Bitboard {
+    let mut attacks = Bitboard(0);
+    for (df, dr) in directions.iter() {
+        let mut curr_sq = *sq;
+        while let Some(to_sq) = offset_square(&curr_sq, *df, *dr) {
+            let to_bb = Bitboard::from(&to_sq);
+            attacks |= to_bb;
+            if !(*occupancy & to_bb).is_empty() {
+                break;
+            }
+            curr_sq = to_sq;
+        }
+    }
+    attacks
+}
+
+// Squares whose occupancy can change the attack set, i.e. the rays without their last square
+fn relevant_mask(sq: &Square, directions: &[(i8, i8)]) -> Bitboard {
+    let mut mask = Bitboard(0);
+    for (df, dr) in directions.iter() {
+        let mut curr_sq = *sq;
+        while let Some(to_sq) = offset_square(&curr_sq, *df, *dr) {
+            if offset_square(&to_sq, *df, *dr).is_none() {
+                break;
+            }
+            mask |= Bitboard::from(&to_sq);
+            curr_sq = to_sq;
+        }
+    }
+    mask
+}
+
+// Fills this square's part of the shared table for every occupancy subset of the mask
+fn init_magic(sq: &Square, directions: &[(i8, i8)], magic: u64, table: &mut Vec<Bitboard>) -> Magic {
+    let mask = relevant_mask(sq, directions);
+    let bits = mask.0.count_ones();
+    let magic = Magic {
+        mask,
+        magic,
+        shift: 64 - bits,
+        offset: table.len(),
+    };
+    table.resize(table.len() + (1 << bits), Bitboard(0));
+
+    // Carry-rippler enumeration of all subsets of the mask
+    let mut subset = Bitboard(0);
+    loop {
+        let idx = magic.index(&subset);
+        let attacks = slider_attacks(sq, &subset, directions);
+        debug_assert!(table[idx] == Bitboard(0) || table[idx] == attacks);
+        table[idx] = attacks;
+        subset = Bitboard(subset.0.wrapping_sub(mask.0) & mask.0);
+        if subset == Bitboard(0) {
+            break;
+        }
+    }
+    magic
+}
+
+impl AttackTables {
+    fn new() -> AttackTables {
+        let mut tables = AttackTables {
+            pawn: [[Bitboard(0); 64]; 2],
+            knight: [Bitboard(0); 64],
+            king: [Bitboard(0); 64],
+            bishop_magics: [EMPTY_MAGIC; 64],
+            rook_magics: [EMPTY_MAGIC; 64],
+            slider_attacks: Vec::new(),
+            between: [[Bitboard(0); 64]; 64],
+            line: [[Bitboard(0); 64]; 64],
+        };
+        for sq in SQUARES.iter() {
+            let idx = sq.0 as usize;
+            tables.pawn[US.0 as usize][idx] = step_attacks(sq, &[(-1, 1), (1, 1)]);
+            tables.pawn[THEM.0 as usize][idx] = step_attacks(sq, &[(-1, -1), (1, -1)]);
+            tables.knight[idx] = step_attacks(sq, &KNIGHT_STEPS);
+            tables.king[idx] = step_attacks(sq, &KING_STEPS);
+            tables.bishop_magics[idx] = init_magic(sq, &BISHOP_DIRECTIONS, BISHOP_MAGICS[idx], &mut tables.slider_attacks);
+            tables.rook_magics[idx] = init_magic(sq, &ROOK_DIRECTIONS, ROOK_MAGICS[idx], &mut tables.slider_attacks);
+        }
+
+        for a in SQUARES.iter() {
+            for b in SQUARES.iter() {
+                let (a_bb, b_bb) = (Bitboard::from(a), Bitboard::from(b));
+                for directions in [&ROOK_DIRECTIONS, &BISHOP_DIRECTIONS].iter() {
+                    if a != b && slider_attacks(a, &Bitboard(0), *directions).contains(b) {
+                        tables.line[a.0 as usize][b.0 as usize] = (slider_attacks(a, &Bitboard(0), *directions)
+                            & slider_attacks(b, &Bitboard(0), *directions))
+                            | a_bb
+                            | b_bb;
+                        tables.between[a.0 as usize][b.0 as usize] =
+                            slider_attacks(a, &b_bb, *directions) & slider_attacks(b, &a_bb, *directions);
+                    }
+                }
+            }
+        }
+        tables
+    }
+}
+
+fn tables() -> &'static AttackTables {
+    TABLES.get_or_init(AttackTables::new)
+}
+
+// Builds the tables up front so the first search does not pay for it
+pub fn init() {
+    tables();
+}
+
+// Squares strictly between a and b when they share a rank, file or diagonal, empty otherwise
+pub fn between(a: &Square, b: &Square) -> Bitboard {
+    tables().between[a.0 as usize][b.0 as usize]
+}
+
+// The whole rank, file or diagonal through a and b, empty when they are not aligned
+pub fn line(a: &Square, b: &Square) -> Bitboard {
+    tables().line[a.0 as usize][b.0 as usize]
+}
+
+// Squares attacked by a pawn of the given color standing on sq, US pawns attack north
+pub fn pawn_attacks(sq: &Square, color: &Color) -> Bitboard {
+    tables().pawn[color.0 as usize][sq.0 as usize]
+}
+
+pub fn bishop_attacks(sq: &Square, occupancy: &Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.slider_attacks[tables.bishop_magics[sq.0 as usize].index(occupancy)]
+}
+
+pub fn rook_attacks(sq: &Square, occupancy: &Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.slider_attacks[tables.rook_magics[sq.0 as usize].index(occupancy)]
+}
+
+// Squares attacked by a non-pawn piece standing on sq
+pub fn attacks(pt: &PieceType, sq: &Square, occupancy: &Bitboard) -> Bitboard {
+    match *pt {
+        KNIGHT => tables().knight[sq.0 as usize],
+        BISHOP => bishop_attacks(sq, occupancy),
+        ROOK => rook_attacks(sq, occupancy),
+        QUEEN => bishop_attacks(sq, occupancy) | rook_attacks(sq, occupancy),
+        KING => tables().king[sq.0 as usize],
+        _ => panic!("Unknown piece type!"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn sq(name: &str) -> Square {
+        Square::try_from(name).unwrap()
+    }
+
+    fn bb(squares: &[&str]) -> Bitboard {
+        squares.iter().fold(Bitboard(0), |bb, name| bb | Bitboard::from(&sq(name)))
+    }
+
+    #[test]
+    fn between_aligned_squares() {
+        assert_eq!(between(&sq("a1"), &sq("d4")), bb(&["b2", "c3"]));
+        assert_eq!(between(&sq("d4"), &sq("a1")), bb(&["b2", "c3"]));
+        assert_eq!(between(&sq("e1"), &sq("e4")), bb(&["e2", "e3"]));
+        assert_eq!(between(&sq("b7"), &sq("f7")), bb(&["c7", "d7", "e7"]));
+        assert!(between(&sq("e1"), &sq("e2")).is_empty());
+        assert!(between(&sq("a1"), &sq("b3")).is_empty());
+    }
+
+    #[test]
+    fn line_through_aligned_squares() {
+        assert_eq!(line(&sq("c3"), &sq("e5")), DIAGONAL_BBS[7]);
+        assert_eq!(line(&sq("b7"), &sq("a8")), ANTI_DIAGONAL_BBS[7]);
+        assert_eq!(line(&sq("e2"), &sq("e7")), FILE_E_BB);
+        assert_eq!(line(&sq("h4"), &sq("a4")), RANK_4_BB);
+        assert!(line(&sq("a1"), &sq("b3")).is_empty());
+        assert!(line(&sq("a1"), &sq("a1")).is_empty());
+    }
+}
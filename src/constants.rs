@@ -1,4 +1,4 @@
-use crate::types::{CastlingRights, Color, FenStage, File, PieceType, Rank, Square};
+use crate::types::{Bitboard, CastlingRights, Color, ColoredPiece, FenStage, File, PieceType, Rank, Square};
 
 // Starting Position FEN
 pub const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
@@ -14,6 +14,15 @@ pub const RANK_6: Rank = Rank(5);
 pub const RANK_7: Rank = Rank(6);
 pub const RANK_8: Rank = Rank(7);
 pub const RANKS: [Rank; 8] = [RANK_1, RANK_2, RANK_3, RANK_4, RANK_5, RANK_6, RANK_7, RANK_8];
+pub const RANK_1_BB: Bitboard = Bitboard(0xff);
+pub const RANK_2_BB: Bitboard = Bitboard(0xff << 8);
+pub const RANK_3_BB: Bitboard = Bitboard(0xff << 16);
+pub const RANK_4_BB: Bitboard = Bitboard(0xff << 24);
+pub const RANK_5_BB: Bitboard = Bitboard(0xff << 32);
+pub const RANK_6_BB: Bitboard = Bitboard(0xff << 40);
+pub const RANK_7_BB: Bitboard = Bitboard(0xff << 48);
+pub const RANK_8_BB: Bitboard = Bitboard(0xff << 56);
+pub const RANK_BBS: [Bitboard; 8] = [RANK_1_BB, RANK_2_BB, RANK_3_BB, RANK_4_BB, RANK_5_BB, RANK_6_BB, RANK_7_BB, RANK_8_BB];
 
 
 // Files
@@ -26,6 +35,35 @@ pub const FILE_F: File = File(5);
 pub const FILE_G: File = File(6);
 pub const FILE_H: File = File(7);
 pub const FILES: [File; 8] = [FILE_A, FILE_B, FILE_C, FILE_D, FILE_E, FILE_F, FILE_G, FILE_H];
+pub const FILE_A_BB: Bitboard = Bitboard(0x0101_0101_0101_0101);
+pub const FILE_B_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 1);
+pub const FILE_C_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 2);
+pub const FILE_D_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 3);
+pub const FILE_E_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 4);
+pub const FILE_F_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 5);
+pub const FILE_G_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 6);
+pub const FILE_H_BB: Bitboard = Bitboard(0x0101_0101_0101_0101 << 7);
+pub const FILE_BBS: [Bitboard; 8] = [FILE_A_BB, FILE_B_BB, FILE_C_BB, FILE_D_BB, FILE_E_BB, FILE_F_BB, FILE_G_BB, FILE_H_BB];
+
+pub const LIGHT_SQUARES_BB: Bitboard = Bitboard(0x55aa_55aa_55aa_55aa);
+pub const DARK_SQUARES_BB: Bitboard = Bitboard(0xaa55_aa55_aa55_aa55);
+
+// Diagonals (a1-h8 direction) indexed by file - rank + 7,
+// anti-diagonals (a8-h1 direction) indexed by file + rank
+pub const DIAGONAL_BBS: [Bitboard; 15] = diagonal_masks(false);
+pub const ANTI_DIAGONAL_BBS: [Bitboard; 15] = diagonal_masks(true);
+
+const fn diagonal_masks(anti: bool) -> [Bitboard; 15] {
+    let mut masks = [Bitboard(0); 15];
+    let mut sq = 0;
+    while sq < 64 {
+        let (file, rank) = (sq & 7, sq >> 3);
+        let idx = if anti { file + rank } else { file + 7 - rank };
+        masks[idx].0 |= 1 << sq;
+        sq += 1;
+    }
+    masks
+}
 
 
 // White base rank squares
@@ -75,6 +113,9 @@ pub const QUEEN: PieceType = PieceType(4);
 pub const KING: PieceType = PieceType(5);
 pub const PIECE_TYPES: [PieceType; 6] = [PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING];
 pub const PIECE_NONE: PieceType = PieceType(6);
+pub const NO_PIECE: ColoredPiece = ColoredPiece(PIECE_NONE, WHITE);
+// Exchange values used by static exchange evaluation, the king is never captured
+pub const SEE_PIECE_VALUES: [i32; 6] = [100, 300, 300, 500, 900, 0];
 
 
 // Castling rights
@@ -101,19 +142,20 @@ pub const MOVE_MASK_ALL: u32 = MOVE_NORMAL | MOVE_CASTLING | MOVE_ENPASSANT
 // Promotion types
 pub const PROM_NONE: u32 = 0;
 pub const PROM_SHIFT: u32 = 19;
-pub const PROM_KNIGHT: u32 = ((KNIGHT.0 as u32) << PROM_SHIFT) as u32;
-pub const PROM_BISHOP: u32 = ((BISHOP.0 as u32) << PROM_SHIFT) as u32;
-pub const PROM_ROOK: u32 = ((ROOK.0 as u32) << PROM_SHIFT) as u32;
-pub const PROM_QUEEN: u32 = ((QUEEN.0 as u32) << PROM_SHIFT) as u32;
+pub const PROM_KNIGHT: u32 = (KNIGHT.0 as u32) << PROM_SHIFT;
+pub const PROM_BISHOP: u32 = (BISHOP.0 as u32) << PROM_SHIFT;
+pub const PROM_ROOK: u32 = (ROOK.0 as u32) << PROM_SHIFT;
+pub const PROM_QUEEN: u32 = (QUEEN.0 as u32) << PROM_SHIFT;
+pub const PROM_TYPES: [u32; 4] = [PROM_QUEEN, PROM_KNIGHT, PROM_ROOK, PROM_BISHOP];
 
 
 // Capture types - only useful if MOVE_CAPTURE bit is set
 pub const CAP_SHIFT: u32 = 22;
-pub const CAP_PAWN: u32 = ((PAWN.0 as u32) << CAP_SHIFT) as u32;
-pub const CAP_KNIGHT: u32 = ((KNIGHT.0 as u32) << CAP_SHIFT) as u32;
-pub const CAP_BISHOP: u32 = ((BISHOP.0 as u32) << CAP_SHIFT) as u32;
-pub const CAP_ROOK: u32 = ((ROOK.0 as u32) << CAP_SHIFT) as u32;
-pub const CAP_QUEEN: u32 = ((QUEEN.0 as u32) << CAP_SHIFT) as u32;
+pub const CAP_PAWN: u32 = (PAWN.0 as u32) << CAP_SHIFT;
+pub const CAP_KNIGHT: u32 = (KNIGHT.0 as u32) << CAP_SHIFT;
+pub const CAP_BISHOP: u32 = (BISHOP.0 as u32) << CAP_SHIFT;
+pub const CAP_ROOK: u32 = (ROOK.0 as u32) << CAP_SHIFT;
+pub const CAP_QUEEN: u32 = (QUEEN.0 as u32) << CAP_SHIFT;
 
 
 // FEN stages
@@ -137,3 +179,9 @@ pub const CASTLING_SPOILERS: [u8; 64] = [
     15, 15, 15, 15, 15, 15, 15, 15,
      7, 15, 15, 15,  3, 15, 15, 11
 ];
+
+// Castling rights in bit order, the first two belong to the side to move
+pub const CASTLING_RIGHTS: [CastlingRights; 4] = [WHITE_KING_SIDE, WHITE_QUEEN_SIDE, BLACK_KING_SIDE, BLACK_QUEEN_SIDE];
+
+// Rook squares of standard chess for each castling right
+pub const DEFAULT_CASTLING_ROOKS: [Square; 4] = [H1, A1, H8, A8];
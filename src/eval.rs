@@ -0,0 +1,218 @@
+use crate::constants::*;
+use crate::position::Position;
+use crate::types::{Color, Square};
+
+pub const MG_PIECE_VALUES: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+pub const EG_PIECE_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+// Contribution of each piece type to the game phase, 24 with all pieces on the board
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Piece-square tables are laid out the way a board is read, rank 8 first, from
+// the side to move's point of view. As the position is always oriented for the
+// mover, our pieces look a square up through sq ^ 56 and theirs use sq directly.
+const MG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const EG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     90,  90,  85,  80,  80,  85,  90,  90,
+     60,  60,  55,  50,  50,  55,  60,  60,
+     35,  35,  30,  25,  25,  30,  35,  35,
+     20,  20,  15,  10,  10,  15,  20,  20,
+     10,  10,   5,   5,   5,   5,  10,  10,
+      5,   5,   0,   0,   0,   0,   5,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const MG_KNIGHT: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+const EG_KNIGHT: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+const MG_BISHOP: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+const EG_BISHOP: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,  10,  15,  15,  10,   5, -10,
+    -10,   5,  10,  15,  15,  10,   5, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+const MG_ROOK: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+const EG_ROOK: [i32; 64] = [
+      5,   5,   5,   5,   5,   5,   5,   5,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const MG_QUEEN: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+const EG_QUEEN: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -10,   5,  10,  10,  10,  10,   5, -10,
+     -5,   5,  10,  15,  15,  10,   5,  -5,
+     -5,   5,  10,  15,  15,  10,   5,  -5,
+    -10,   5,  10,  10,  10,  10,   5, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+const MG_KING: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+const EG_KING: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+const MG_TABLES: [[i32; 64]; 6] = [MG_PAWN, MG_KNIGHT, MG_BISHOP, MG_ROOK, MG_QUEEN, MG_KING];
+const EG_TABLES: [[i32; 64]; 6] = [EG_PAWN, EG_KNIGHT, EG_BISHOP, EG_ROOK, EG_QUEEN, EG_KING];
+
+fn table_index(sq: &Square, color: &Color) -> usize {
+    if *color == US {
+        (sq.0 ^ 56) as usize
+    } else {
+        sq.0 as usize
+    }
+}
+
+// Game phase between 0 (pawns and kings only) and MAX_PHASE (all pieces present)
+pub fn game_phase(pos: &Position) -> i32 {
+    let phase: i32 = PIECE_TYPES
+        .iter()
+        .map(|pt| PHASE_WEIGHTS[pt.0 as usize] * pos.pieces(pt).popcount() as i32)
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// Static evaluation in centipawns from the side to move's point of view
+pub fn evaluate(pos: &Position) -> i32 {
+    let mut mg = 0;
+    let mut eg = 0;
+    for pt in PIECE_TYPES.iter() {
+        let pt_idx = pt.0 as usize;
+        for (color, sign) in [(US, 1), (THEM, -1)].iter() {
+            for sq in pos.pieces_of(pt, color) {
+                let idx = table_index(&sq, color);
+                mg += sign * (MG_PIECE_VALUES[pt_idx] + MG_TABLES[pt_idx][idx]);
+                eg += sign * (EG_PIECE_VALUES[pt_idx] + EG_TABLES[pt_idx][idx]);
+            }
+        }
+    }
+    let phase = game_phase(pos);
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_position_is_balanced() {
+        let pos = Position::from(INITIAL_FEN);
+        assert_eq!(game_phase(&pos), MAX_PHASE);
+        assert_eq!(evaluate(&pos), 0);
+    }
+
+    #[test]
+    fn colors_are_symmetric() {
+        // The same position with colors swapped scores the same for the mover
+        let black = Position::from("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        let white = Position::from("rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(evaluate(&black), evaluate(&white));
+        assert!(evaluate(&white) < 0);
+    }
+
+    #[test]
+    fn material_counts_for_the_side_to_move() {
+        let white = Position::from("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        let black = Position::from("4k3/8/8/8/8/8/8/3QK3 b - - 0 1");
+        assert!(evaluate(&white) > EG_PIECE_VALUES[QUEEN.0 as usize] / 2);
+        assert!(evaluate(&black) < -EG_PIECE_VALUES[QUEEN.0 as usize] / 2);
+    }
+
+    #[test]
+    fn phase_tapers_to_the_endgame() {
+        assert_eq!(game_phase(&Position::from("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1")), 0);
+        assert_eq!(game_phase(&Position::from("3qk3/8/8/8/8/8/8/3QK3 w - - 0 1")), 8);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::position::Position;
+use crate::types::Move;
+
+// A position together with the moves that led to it, needed for the draw
+// rules that depend on more than the current board
+#[derive(Clone)]
+pub struct Game {
+    position: Position,
+    // Positions before each move played
+    history: Vec<Position>,
+    moves: Vec<Move>,
+    // Zobrist keys of every position in the game, the current one last
+    keys: Vec<u64>,
+}
+
+impl Game {
+    pub fn new(position: Position) -> Game {
+        Game {
+            position,
+            history: Vec::new(),
+            moves: Vec::new(),
+            keys: vec![position.hash()],
+        }
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn keys(&self) -> &[u64] {
+        &self.keys
+    }
+
+    pub fn make_move(&mut self, m: &Move) {
+        self.history.push(self.position);
+        self.moves.push(*m);
+        self.position = self.position.make_move(m);
+        self.keys.push(self.position.hash());
+    }
+
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let position = self.history.pop()?;
+        self.position = position;
+        self.keys.pop();
+        self.moves.pop()
+    }
+
+    // Times the current position occurred before. Only positions with the same
+    // side to move since the last irreversible move are candidates.
+    fn repetitions(&self) -> usize {
+        let current = self.keys.len() - 1;
+        let window = (self.position.halfmoves() as usize).min(current);
+        (2..=window)
+            .step_by(2)
+            .filter(|distance| self.keys[current - distance] == self.keys[current])
+            .count()
+    }
+
+    pub fn is_repetition(&self) -> bool {
+        self.repetitions() >= 1
+    }
+
+    pub fn is_threefold(&self) -> bool {
+        self.repetitions() >= 2
+    }
+
+    // A checkmate delivered on the hundredth half-move still ends the game
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.position.halfmoves() >= 100 && !(self.position.in_check() && self.position.legal_moves().is_empty())
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        self.position.is_insufficient_material()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::INITIAL_FEN;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for name in moves.iter() {
+            let m = game.position().parse_uci_move(name).unwrap();
+            game.make_move(&m);
+        }
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game = Game::new(Position::from(INITIAL_FEN));
+        play(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert!(game.is_repetition());
+        assert!(!game.is_threefold());
+        play(&mut game, &["g1f3", "g8f6", "f3g1"]);
+        assert!(!game.is_threefold());
+        play(&mut game, &["f6g8"]);
+        assert!(game.is_threefold());
+        game.undo_move();
+        assert!(!game.is_threefold());
+        assert_eq!(game.moves().len(), 7);
+    }
+
+    #[test]
+    fn irreversible_move_resets_repetitions() {
+        let mut game = Game::new(Position::from(INITIAL_FEN));
+        play(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8", "e2e3", "e7e6"]);
+        play(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert!(game.is_repetition());
+        assert!(!game.is_threefold());
+    }
+
+    #[test]
+    fn fifty_move_draw() {
+        let mut game = Game::new(Position::from("7k/8/6K1/8/8/8/8/R7 w - - 99 80"));
+        assert!(!game.is_fifty_move_draw());
+        play(&mut game, &["a1a2"]);
+        assert_eq!(game.position().halfmoves(), 100);
+        assert!(game.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn mate_on_the_hundredth_half_move() {
+        let mut game = Game::new(Position::from("7k/8/6K1/8/8/8/8/R7 w - - 99 80"));
+        play(&mut game, &["a1a8"]);
+        assert_eq!(game.position().halfmoves(), 100);
+        assert!(!game.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn move_clocks() {
+        let mut game = Game::new(Position::from(INITIAL_FEN));
+        play(&mut game, &["g1f3", "g8f6"]);
+        assert_eq!(game.position().halfmoves(), 2);
+        assert_eq!(game.position().fullmoves(), 2);
+        play(&mut game, &["e2e4"]);
+        assert_eq!(game.position().halfmoves(), 0);
+        assert_eq!(game.position().fullmoves(), 2);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        assert!(Game::new(Position::from("8/8/8/4k3/8/8/8/4KN2 w - - 0 1")).is_insufficient_material());
+        assert!(!Game::new(Position::from("8/8/8/4k3/8/8/8/4KR2 w - - 0 1")).is_insufficient_material());
+    }
+}
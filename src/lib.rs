@@ -0,0 +1,34 @@
+// Board representation, move generation, notation and search of the Nirsyde
+// engine. Call attacks::init once before using anything that generates moves,
+// otherwise the attack tables are built on first use.
+
+pub mod attacks;
+pub mod constants;
+pub mod eval;
+pub mod game;
+pub mod movegen;
+pub mod movepick;
+pub mod perft;
+pub mod pgn;
+pub mod position;
+pub mod search;
+pub mod timeman;
+pub mod tt;
+pub mod type_traits;
+pub mod types;
+pub mod uci;
+pub mod zobrist;
+
+mod notation;
+mod see;
+mod type_operator_overloads;
+mod type_trait_impls;
+mod validate;
+
+pub use crate::game::Game;
+pub use crate::movegen::MoveList;
+pub use crate::position::Position;
+pub use crate::types::{
+    Bitboard, CastlingRights, Color, ColoredPiece, FenError, File, GameResult, Move, MoveError, PgnError, PieceType,
+    Rank, Square, Violation,
+};
@@ -1,19 +1,47 @@
-#![allow(dead_code)]
+use std::env;
+use std::fs;
+use std::process;
 
-use crate::constants::*;
-use crate::position::Position;
-use crate::type_traits::Printable;
-use crate::types::{Move, Square};
-
-mod position;
-mod types;
-mod constants;
-mod type_trait_impls;
-mod type_traits;
-mod type_operator_overloads;
+use nirsyde::constants::INITIAL_FEN;
+use nirsyde::{attacks, perft, pgn, uci, Position};
 
 fn main() {
-    let pos = Position::from(INITIAL_FEN);
-    let npos = pos.make_move(&Move::normal(&Square(1), &Square(18)));
-    npos.print_parts();
+    attacks::init();
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("perftsuite") => {
+            if !perft::run_suite() {
+                process::exit(1);
+            }
+        }
+        Some("perftbench") => {
+            if !perft::compare_make_strategies() {
+                process::exit(1);
+            }
+        }
+        Some("divide") => {
+            let depth = args.get(2).and_then(|d| d.parse::<u32>().ok()).unwrap_or(1);
+            let fen = if args.len() > 3 { args[3..].join(" ") } else { String::from(INITIAL_FEN) };
+            Position::from(&fen).divide(depth);
+        }
+        Some("pgn") => {
+            let path = args.get(2).map(String::as_str).unwrap_or_default();
+            let text = fs::read_to_string(path).unwrap_or_else(|err| {
+                println!("{}: {}", path, err);
+                process::exit(1);
+            });
+            match pgn::parse(&text) {
+                Ok(games) => {
+                    for game in games.iter() {
+                        println!("{}", game);
+                    }
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        _ => uci::run(),
+    }
 }
@@ -0,0 +1,192 @@
+use crate::attacks::{attacks, between, pawn_attacks};
+use crate::constants::*;
+use crate::position::{castling_destinations, Position};
+use crate::types::{Bitboard, Move, PieceType, Square};
+
+pub const MAX_MOVES: usize = 256;
+
+// Fixed-capacity move list, no position can have more than 218 legal moves
+#[derive(Copy, Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            moves: [Move(0); MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, m: Move) {
+        self.moves[self.len] = m;
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, m: &Move) -> bool {
+        self.iter().any(|x| x == m)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Move> {
+        self.moves[..self.len].iter()
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+fn add_promotions(from_sq: &Square, to_sq: &Square, cap_type: Option<u32>, moves: &mut MoveList) {
+    for prom_type in PROM_TYPES.iter() {
+        match cap_type {
+            Some(cap_type) => moves.push(Move::promotion_capture(from_sq, to_sq, *prom_type, cap_type)),
+            None => moves.push(Move::promotion(from_sq, to_sq, *prom_type)),
+        }
+    }
+}
+
+// Pushes are generated set-wise, the origin square is recovered from the target
+fn add_pawn_moves(pos: &Position, moves: &mut MoveList) {
+    let empty = !pos.occupied();
+    let them = pos.occupancy(&THEM);
+    let pawns = pos.pieces_of(&PAWN, &US);
+
+    let single_pushes = pawns.north() & empty;
+    for to_sq in single_pushes & !RANK_8_BB {
+        moves.push(Move::normal(&(to_sq - Square(8)), &to_sq));
+    }
+    for to_sq in single_pushes & RANK_8_BB {
+        add_promotions(&(to_sq - Square(8)), &to_sq, None, moves);
+    }
+    for to_sq in (single_pushes & RANK_3_BB).north() & empty {
+        moves.push(Move::double_push(&(to_sq - Square(16)), &to_sq));
+    }
+
+    for from_sq in pawns {
+        for to_sq in pawn_attacks(&from_sq, &US) & them {
+            let cap_type = (pos.piece_type_on(&to_sq).0 as u32) << CAP_SHIFT;
+            if to_sq.rank() == RANK_8 {
+                add_promotions(&from_sq, &to_sq, Some(cap_type), moves);
+            } else {
+                moves.push(Move::capture(&from_sq, &to_sq, cap_type));
+            }
+        }
+    }
+
+    let ep_sq = pos.enpassant_square();
+    if ep_sq != SQUARE_INVALID {
+        for from_sq in pawn_attacks(&ep_sq, &THEM) & pawns {
+            moves.push(Move::enpassant(&from_sq, &ep_sq));
+        }
+    }
+}
+
+fn add_piece_moves(pos: &Position, pt: &PieceType, moves: &mut MoveList) {
+    let occupancy = pos.occupied();
+    let them = pos.occupancy(&THEM);
+    for from_sq in pos.pieces_of(pt, &US) {
+        for to_sq in attacks(pt, &from_sq, &occupancy) & !pos.occupancy(&US) {
+            if them.contains(&to_sq) {
+                let cap_type = (pos.piece_type_on(&to_sq).0 as u32) << CAP_SHIFT;
+                moves.push(Move::capture(&from_sq, &to_sq, cap_type));
+            } else {
+                moves.push(Move::normal(&from_sq, &to_sq));
+            }
+        }
+    }
+}
+
+// Castling is only generated when the king does not start on or pass through
+// an attacked square and every square both pieces cross or land on is empty
+// apart from the king and rook themselves. The landing square is left to the
+// legality check, which sees the board with the rook already moved.
+fn add_castling_moves(pos: &Position, moves: &mut MoveList) {
+    let castling_rights = pos.castling_rights();
+    if !castling_rights.allows(WHITE_KING_SIDE) && !castling_rights.allows(WHITE_QUEEN_SIDE) {
+        return;
+    }
+    let king_sq = pos.king_square(&US);
+    if king_sq.rank() != RANK_1 || pos.in_check() {
+        return;
+    }
+
+    for right in [WHITE_KING_SIDE, WHITE_QUEEN_SIDE].iter() {
+        if !castling_rights.allows(*right) {
+            continue;
+        }
+        let rook_sq = pos.castling_rook(right);
+        if !pos.pieces_of(&ROOK, &US).contains(&rook_sq) {
+            continue;
+        }
+        let (kto_sq, rto_sq) = castling_destinations(&king_sq, &rook_sq);
+        let king_path = between(&king_sq, &kto_sq) | Bitboard::from(&kto_sq);
+        let rook_path = between(&rook_sq, &rto_sq) | Bitboard::from(&rto_sq);
+        let occupancy = pos.occupied() ^ Bitboard::from(&king_sq) ^ Bitboard::from(&rook_sq);
+        if !((king_path | rook_path | between(&king_sq, &rook_sq)) & occupancy).is_empty() {
+            continue;
+        }
+        if (king_path ^ Bitboard::from(&kto_sq)).any(|sq| pos.is_square_attacked(&sq, &THEM)) {
+            continue;
+        }
+        moves.push(Move::castle(&king_sq, &rook_sq));
+    }
+}
+
+impl Position {
+    // All moves for the side to move which may still leave its own king in check
+    pub fn pseudo_legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        add_pawn_moves(self, &mut moves);
+        for pt in [KNIGHT, BISHOP, ROOK, QUEEN, KING].iter() {
+            add_piece_moves(self, pt, &mut moves);
+        }
+        add_castling_moves(self, &mut moves);
+        moves
+    }
+
+    // Pseudo-legal moves filtered down to those that do not leave the king in check,
+    // which takes care of pins, check evasions and en-passant discovered checks
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        let pinned = self.pinned_pieces();
+        let in_check = self.in_check();
+        for m in self.pseudo_legal_moves().iter() {
+            if self.is_legal_with(m, &pinned, in_check) {
+                moves.push(*m);
+            }
+        }
+        moves
+    }
+
+    pub fn is_legal(&self, m: &Move) -> bool {
+        self.is_legal_with(m, &self.pinned_pieces(), self.in_check())
+    }
+
+    // Moves of unpinned pieces other than the king cannot expose it, unless
+    // we are already in check or the move is en passant
+    fn is_legal_with(&self, m: &Move, pinned: &Bitboard, in_check: bool) -> bool {
+        let from_sq = m.from_square();
+        if !in_check
+            && !pinned.contains(&from_sq)
+            && from_sq != self.king_square(&US)
+            && !m.is_move_type(MOVE_ENPASSANT)
+        {
+            return true;
+        }
+        // After make_move the board is flipped, so our king now belongs to THEM
+        let pos = self.make_move(m);
+        !pos.is_square_attacked(&pos.king_square(&THEM), &US)
+    }
+}
@@ -0,0 +1,379 @@
+use crate::constants::*;
+use crate::movegen::{MoveList, MAX_MOVES};
+use crate::position::Position;
+use crate::search::MAX_PLY;
+use crate::types::{Move, PieceType, Square};
+
+pub const MAX_HISTORY: i32 = 16384;
+
+// Moved piece and destination of an earlier move, the key of the counter-move
+// and continuation tables
+pub type MoveContext = (PieceType, Square);
+
+const CONTINUATION_SIZE: usize = 6 * 64 * 6 * 64;
+
+// Ordering statistics gathered by one search thread
+pub struct History {
+    killers: [[Move; 2]; MAX_PLY],
+    counter_moves: [[Move; 64]; 6],
+    // Butterfly table indexed by origin and destination, squares are seen from
+    // the mover so both colors share it
+    butterfly: Box<[[i32; 64]; 64]>,
+    continuation: Vec<i32>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            killers: [[Move(0); 2]; MAX_PLY],
+            counter_moves: [[Move(0); 64]; 6],
+            butterfly: Box::new([[0; 64]; 64]),
+            continuation: vec![0; CONTINUATION_SIZE],
+        }
+    }
+
+    pub fn killers(&self, ply: usize) -> [Move; 2] {
+        self.killers[ply]
+    }
+
+    pub fn counter_move(&self, prev: &Option<MoveContext>) -> Move {
+        prev.map_or(Move(0), |(pt, sq)| self.counter_moves[pt.0 as usize][sq.0 as usize])
+    }
+
+    fn continuation_index(prev: &MoveContext, pt: &PieceType, to: &Square) -> usize {
+        ((prev.0 .0 as usize * 64 + prev.1 .0 as usize) * 6 + pt.0 as usize) * 64 + to.0 as usize
+    }
+
+    // Butterfly score plus the continuation scores after the last two moves
+    pub fn quiet_score(&self, m: &Move, pt: &PieceType, context: &[Option<MoveContext>; 2]) -> i32 {
+        let to = m.to_square();
+        let mut score = self.butterfly[m.from_square().0 as usize][to.0 as usize];
+        for prev in context.iter().flatten() {
+            score += self.continuation[History::continuation_index(prev, pt, &to)];
+        }
+        score
+    }
+
+    // Gravity keeps every entry within MAX_HISTORY, large values move less
+    fn apply_bonus(entry: &mut i32, bonus: i32) {
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+
+    // The quiet move m caused a beta cutoff, the quiets searched before it did not
+    pub fn update_quiet(
+        &mut self,
+        pos: &Position,
+        m: &Move,
+        ply: usize,
+        depth: i32,
+        context: &[Option<MoveContext>; 2],
+        tried: &MoveList,
+    ) {
+        if self.killers[ply][0] != *m {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = *m;
+        }
+        if let Some((pt, sq)) = context[0] {
+            self.counter_moves[pt.0 as usize][sq.0 as usize] = *m;
+        }
+
+        let bonus = (depth * depth * 4).min(MAX_HISTORY / 8);
+        for quiet in tried.iter() {
+            let delta = if quiet == m { bonus } else { -bonus };
+            let pt = pos.piece_type_on(&quiet.from_square());
+            let to = quiet.to_square();
+            History::apply_bonus(&mut self.butterfly[quiet.from_square().0 as usize][to.0 as usize], delta);
+            for prev in context.iter().flatten() {
+                History::apply_bonus(&mut self.continuation[History::continuation_index(prev, &pt, &to)], delta);
+            }
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Stage {
+    TtMove,
+    ScoreCaptures,
+    Captures,
+    Killers,
+    CounterMove,
+    ScoreQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+const UNSCORED: i32 = i32::MIN;
+
+// Hands out legal moves in stages: the hash move, captures and promotions by
+// MVV-LVA, the killers, the counter-move, quiets by history and finally the
+// captures that lose material. Each stage picks its best move on demand, so a
+// cutoff skips sorting the rest.
+pub struct MovePicker {
+    moves: [Move; MAX_MOVES],
+    scores: [i32; MAX_MOVES],
+    len: usize,
+    stage: Stage,
+    captures_only: bool,
+    tt_move: u16,
+    killers: [Move; 2],
+    killer_idx: usize,
+    counter_move: Move,
+    context: [Option<MoveContext>; 2],
+    bad_captures: MoveList,
+    bad_capture_idx: usize,
+}
+
+impl MovePicker {
+    pub fn new(
+        moves: &MoveList,
+        tt_move: u16,
+        killers: [Move; 2],
+        counter_move: Move,
+        context: [Option<MoveContext>; 2],
+    ) -> MovePicker {
+        let mut picker = MovePicker {
+            moves: [Move(0); MAX_MOVES],
+            scores: [UNSCORED; MAX_MOVES],
+            len: 0,
+            stage: Stage::TtMove,
+            captures_only: false,
+            tt_move,
+            killers,
+            killer_idx: 0,
+            counter_move,
+            context,
+            bad_captures: MoveList::new(),
+            bad_capture_idx: 0,
+        };
+        for m in moves.iter() {
+            picker.moves[picker.len] = *m;
+            picker.len += 1;
+        }
+        picker
+    }
+
+    // Quiescence only looks at the captures in the list, best victims first
+    pub fn captures(moves: &MoveList) -> MovePicker {
+        let mut picker = MovePicker::new(moves, 0, [Move(0); 2], Move(0), [None; 2]);
+        picker.stage = Stage::ScoreCaptures;
+        picker.captures_only = true;
+        picker
+    }
+
+    fn is_tactical(m: &Move) -> bool {
+        m.is_move_type(MOVE_MASK_CAPTURE | MOVE_PROMOTION)
+    }
+
+    // Most valuable victim first, least valuable attacker among equal victims,
+    // promotions ranked by the new piece
+    fn mvv_lva(pos: &Position, m: &Move) -> i32 {
+        let mut score = 0;
+        if m.is_move_type(MOVE_MASK_CAPTURE) {
+            score += (m.capture_type().0 as i32 + 1) * 8 - pos.piece_type_on(&m.from_square()).0 as i32;
+        }
+        if m.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP) {
+            score += m.promotion_type().0 as i32 * 8;
+        }
+        score
+    }
+
+    fn take(&mut self, idx: usize) -> Move {
+        let m = self.moves[idx];
+        self.len -= 1;
+        self.moves.swap(idx, self.len);
+        self.scores.swap(idx, self.len);
+        m
+    }
+
+    fn take_move(&mut self, m: &Move) -> Option<Move> {
+        if m.0 == 0 {
+            return None;
+        }
+        let idx = self.moves[..self.len].iter().position(|other| other == m)?;
+        Some(self.take(idx))
+    }
+
+    fn take_best(&mut self) -> Option<Move> {
+        let (idx, score) = self.scores[..self.len]
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, score)| **score)
+            .map(|(idx, score)| (idx, *score))?;
+        if score == UNSCORED {
+            return None;
+        }
+        Some(self.take(idx))
+    }
+
+    pub fn next(&mut self, pos: &Position, history: &History) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::ScoreCaptures;
+                    let tt_move = self.tt_move;
+                    if tt_move != 0 {
+                        if let Some(idx) = self.moves[..self.len].iter().position(|m| m.compressed() == tt_move) {
+                            return Some(self.take(idx));
+                        }
+                    }
+                }
+                Stage::ScoreCaptures => {
+                    for idx in 0..self.len {
+                        let m = self.moves[idx];
+                        if MovePicker::is_tactical(&m) {
+                            self.scores[idx] = MovePicker::mvv_lva(pos, &m);
+                        }
+                    }
+                    self.stage = Stage::Captures;
+                }
+                Stage::Captures => match self.take_best() {
+                    // Losing captures wait until after the quiets
+                    Some(m) if !self.captures_only && !pos.see_ge(&m, 0) => self.bad_captures.push(m),
+                    Some(m) => return Some(m),
+                    None if self.captures_only => self.stage = Stage::Done,
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    if self.killer_idx == self.killers.len() {
+                        self.stage = Stage::CounterMove;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_idx];
+                    self.killer_idx += 1;
+                    if let Some(m) = self.take_move(&killer) {
+                        return Some(m);
+                    }
+                }
+                Stage::CounterMove => {
+                    self.stage = Stage::ScoreQuiets;
+                    let counter_move = self.counter_move;
+                    if let Some(m) = self.take_move(&counter_move) {
+                        return Some(m);
+                    }
+                }
+                Stage::ScoreQuiets => {
+                    for idx in 0..self.len {
+                        let m = self.moves[idx];
+                        let pt = pos.piece_type_on(&m.from_square());
+                        // Bounded by the history limits, so never UNSCORED
+                        self.scores[idx] = history.quiet_score(&m, &pt, &self.context);
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.take_best() {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => match self.bad_captures.iter().nth(self.bad_capture_idx) {
+                    Some(m) => {
+                        self.bad_capture_idx += 1;
+                        return Some(*m);
+                    }
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn uci(pos: &Position, name: &str) -> Move {
+        pos.parse_uci_move(name).unwrap()
+    }
+
+    fn drain(picker: &mut MovePicker, pos: &Position, history: &History) -> Vec<Move> {
+        let mut moves = Vec::new();
+        while let Some(m) = picker.next(pos, history) {
+            moves.push(m);
+        }
+        moves
+    }
+
+    #[test]
+    fn stage_order() {
+        let pos = Position::from(KIWIPETE);
+        let legal = pos.legal_moves();
+        let tt_move = uci(&pos, "a2a3");
+        let killers = [uci(&pos, "g2g3"), uci(&pos, "e1d1")];
+        let counter_move = uci(&pos, "a1b1");
+        let history = History::new();
+        let mut picker = MovePicker::new(&legal, tt_move.compressed(), killers, counter_move, [None; 2]);
+        let picked = drain(&mut picker, &pos, &history);
+
+        // Every legal move exactly once
+        assert_eq!(picked.len(), legal.len());
+        assert!(legal.iter().all(|m| picked.contains(m)));
+
+        assert!(picked[0] == tt_move);
+        // Captures that hold their material come first, the losing ones last
+        let is_good = |m: &Move| MovePicker::is_tactical(m) && pos.see_ge(m, 0);
+        let is_bad = |m: &Move| MovePicker::is_tactical(m) && !pos.see_ge(m, 0);
+        let captures = picked[1..].iter().take_while(|m| is_good(m)).count();
+        assert_eq!(captures, legal.iter().filter(|m| is_good(m)).count());
+        let bad_captures = picked.iter().rev().take_while(|m| is_bad(m)).count();
+        assert_eq!(bad_captures, legal.iter().filter(|m| is_bad(m)).count());
+        assert!(bad_captures > 0);
+        for group in [&picked[1..=captures], &picked[picked.len() - bad_captures..]].iter() {
+            let scores: Vec<i32> = group.iter().map(|m| MovePicker::mvv_lva(&pos, m)).collect();
+            assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+        }
+        assert!(picked[captures + 1] == killers[0]);
+        assert!(picked[captures + 2] == killers[1]);
+        assert!(picked[captures + 3] == counter_move);
+        assert!(picked[captures + 4..picked.len() - bad_captures].iter().all(|m| !MovePicker::is_tactical(m)));
+    }
+
+    #[test]
+    fn moves_not_in_the_list_are_skipped() {
+        let pos = Position::from(INITIAL_FEN);
+        let legal = pos.legal_moves();
+        // Both killers from another position, one of them also the hash move
+        let other = Position::from(KIWIPETE);
+        let killers = [uci(&other, "e5f7"), uci(&pos, "g1f3")];
+        let mut picker = MovePicker::new(&legal, killers[1].compressed(), killers, Move(0), [None; 2]);
+        let picked = drain(&mut picker, &pos, &History::new());
+        assert_eq!(picked.len(), legal.len());
+        assert!(picked[0] == killers[1]);
+    }
+
+    #[test]
+    fn captures_only() {
+        let pos = Position::from(KIWIPETE);
+        let legal = pos.legal_moves();
+        let picked = drain(&mut MovePicker::captures(&legal), &pos, &History::new());
+        assert_eq!(picked.len(), legal.iter().filter(|m| MovePicker::is_tactical(m)).count());
+        // The bishop on a6 is the most valuable piece en prise
+        assert!(picked[0] == uci(&pos, "e2a6"));
+    }
+
+    #[test]
+    fn history_orders_quiets() {
+        let pos = Position::from(INITIAL_FEN);
+        let legal = pos.legal_moves();
+        let mut history = History::new();
+        let mut tried = MoveList::new();
+        tried.push(uci(&pos, "a2a3"));
+        tried.push(uci(&pos, "h2h3"));
+        history.update_quiet(&pos, &uci(&pos, "h2h3"), 0, 4, &[None; 2], &tried);
+        assert!(history.killers(0)[0] == uci(&pos, "h2h3"));
+
+        // Killers of another ply, so history alone decides
+        let mut picker = MovePicker::new(&legal, 0, history.killers(1), Move(0), [None; 2]);
+        let picked = drain(&mut picker, &pos, &history);
+        assert!(picked[0] == uci(&pos, "h2h3"));
+        assert!(picked[picked.len() - 1] == uci(&pos, "a2a3"));
+    }
+}
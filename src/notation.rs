@@ -0,0 +1,396 @@
+use std::convert::TryFrom;
+
+use crate::constants::*;
+use crate::position::{castling_destinations, Position};
+use crate::types::{File, Move, MoveError, PieceType, Rank, Square};
+
+fn parse_square(chars: &[char]) -> Option<Square> {
+    let file = File::try_from(&chars[0]).ok()?;
+    let rank = Rank::try_from(&chars[1]).ok()?;
+    Some(Square::new(&file, &rank))
+}
+
+impl Position {
+    // Squares are mirrored between the real board and the mover's point of view
+    // while black is to move
+    fn orient(&self, sq: &Square) -> Square {
+        if self.side_to_move() == BLACK {
+            Square(sq.0 ^ 56)
+        } else {
+            *sq
+        }
+    }
+
+    // Long algebraic notation as used by UCI, e.g. e2e4, e7e8q or e1g1. Castling
+    // is written as the king capturing its rook in Chess960. Move
+    // has no Display of its own as its squares only make sense with the side to move.
+    pub fn move_to_uci(&self, m: &Move) -> String {
+        let mut to_sq = m.to_square();
+        if m.is_move_type(MOVE_CASTLING) && !self.is_chess960() {
+            to_sq = castling_destinations(&m.from_square(), &to_sq).0;
+        }
+        let mut name = String::from(&self.orient(&m.from_square()));
+        name.push_str(&String::from(&self.orient(&to_sq)));
+        if m.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP) {
+            name.push(char::from(&m.promotion_type()));
+        }
+        name
+    }
+
+    // The move flags and captured piece type are resolved from the board and the
+    // result is checked against the legal moves
+    pub fn parse_uci_move(&self, name: &str) -> Result<Move, MoveError> {
+        let chars: Vec<char> = name.chars().collect();
+        if chars.len() != 4 && chars.len() != 5 {
+            return Err(MoveError::IllegalMove(String::from(name)));
+        }
+        let from_sq = parse_square(&chars[0..2]).ok_or_else(|| MoveError::InvalidSquare(chars[0..2].iter().collect()))?;
+        let to_sq = parse_square(&chars[2..4]).ok_or_else(|| MoveError::InvalidSquare(chars[2..4].iter().collect()))?;
+        let prom_type = match chars.get(4) {
+            Some(c) => match PieceType::try_from(c) {
+                Ok(pt) if pt == KNIGHT || pt == BISHOP || pt == ROOK || pt == QUEEN => Some((pt.0 as u32) << PROM_SHIFT),
+                _ => return Err(MoveError::InvalidPromotion(*c)),
+            },
+            None => None,
+        };
+
+        let from_sq = self.orient(&from_sq);
+        let to_sq = self.orient(&to_sq);
+        if !self.occupancy(&US).contains(&from_sq) {
+            return Err(MoveError::NoPieceOnSquare(String::from(&self.orient(&from_sq))));
+        }
+
+        let moving_pt = self.piece_type_on(&from_sq);
+        let cap_type = if self.occupancy(&THEM).contains(&to_sq) {
+            Some((self.piece_type_on(&to_sq).0 as u32) << CAP_SHIFT)
+        } else {
+            None
+        };
+        let m = match (prom_type, cap_type) {
+            (Some(prom_type), Some(cap_type)) => Move::promotion_capture(&from_sq, &to_sq, prom_type, cap_type),
+            (Some(prom_type), None) => Move::promotion(&from_sq, &to_sq, prom_type),
+            (None, Some(cap_type)) => Move::capture(&from_sq, &to_sq, cap_type),
+            (None, None) if moving_pt == KING && self.pieces_of(&ROOK, &US).contains(&to_sq) => {
+                Move::castle(&from_sq, &to_sq)
+            }
+            (None, None) if moving_pt == KING && !self.is_chess960() && from_sq == E1 && to_sq == G1 => {
+                Move::castle(&from_sq, &self.castling_rook(&WHITE_KING_SIDE))
+            }
+            (None, None) if moving_pt == KING && !self.is_chess960() && from_sq == E1 && to_sq == C1 => {
+                Move::castle(&from_sq, &self.castling_rook(&WHITE_QUEEN_SIDE))
+            }
+            (None, None) if moving_pt == PAWN && to_sq == self.enpassant_square() => Move::enpassant(&from_sq, &to_sq),
+            (None, None) if moving_pt == PAWN && to_sq.0 == from_sq.0 + 16 => Move::double_push(&from_sq, &to_sq),
+            (None, None) => Move::normal(&from_sq, &to_sq),
+        };
+
+        if !self.legal_moves().contains(&m) {
+            return Err(MoveError::IllegalMove(String::from(name)));
+        }
+        Ok(m)
+    }
+
+    // Standard algebraic notation, e.g. Nf3, exd5, O-O-O, e8=Q+ or Raxd1#
+    pub fn move_to_san(&self, m: &Move) -> String {
+        let from_sq = m.from_square();
+        let to_sq = m.to_square();
+        let mut san = String::new();
+        if m.is_move_type(MOVE_CASTLING) {
+            san.push_str(if to_sq.0 > from_sq.0 { "O-O" } else { "O-O-O" });
+        } else {
+            let moving_pt = self.piece_type_on(&from_sq);
+            let is_capture = m.is_move_type(MOVE_MASK_CAPTURE);
+            if moving_pt == PAWN {
+                if is_capture {
+                    san.push(char::from(&self.orient(&from_sq).file()));
+                }
+            } else {
+                san.push(char::from(&moving_pt).to_ascii_uppercase());
+                san.push_str(&self.disambiguation(m, &moving_pt));
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&String::from(&self.orient(&to_sq)));
+            if m.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP) {
+                san.push('=');
+                san.push(char::from(&m.promotion_type()).to_ascii_uppercase());
+            }
+        }
+
+        let pos = self.make_move(m);
+        if pos.in_check() {
+            san.push(if pos.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        san
+    }
+
+    // File, rank or both of the origin square, whichever is needed to tell the
+    // move apart from moves of other pieces of the same type to the same square
+    fn disambiguation(&self, m: &Move, moving_pt: &PieceType) -> String {
+        let from_sq = m.from_square();
+        let others: Vec<Square> = self
+            .legal_moves()
+            .iter()
+            .filter(|other| other.to_square() == m.to_square() && other.from_square() != from_sq)
+            .map(|other| other.from_square())
+            .filter(|sq| self.piece_type_on(sq) == *moving_pt)
+            .collect();
+        let real_from_sq = self.orient(&from_sq);
+        let file_ch = char::from(&real_from_sq.file());
+        let rank_ch = char::from(&real_from_sq.rank());
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|sq| sq.file() != from_sq.file()) {
+            file_ch.to_string()
+        } else if others.iter().all(|sq| sq.rank() != from_sq.rank()) {
+            rank_ch.to_string()
+        } else {
+            [file_ch, rank_ch].iter().collect()
+        }
+    }
+
+    // Accepts over-disambiguated moves, a missing '=' before the promotion
+    // piece and trailing check or annotation symbols
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveError> {
+        let illegal = || MoveError::IllegalMove(String::from(san));
+        let text = san.trim_end_matches(['+', '#', '!', '?']);
+        let mut chars: Vec<char> = text.chars().filter(|c| *c != 'x' && *c != '=' && *c != '-').collect();
+
+        if text == "O-O" || text == "0-0" || text == "O-O-O" || text == "0-0-0" {
+            let king_side = text.len() == 3;
+            return self
+                .legal_moves()
+                .iter()
+                .find(|m| m.is_move_type(MOVE_CASTLING) && (m.to_square().0 > m.from_square().0) == king_side)
+                .copied()
+                .ok_or_else(illegal);
+        }
+
+        let moving_pt = match chars.first() {
+            Some(c) if c.is_ascii_uppercase() => {
+                let pt = PieceType::try_from(c).map_err(|_| illegal())?;
+                chars.remove(0);
+                pt
+            }
+            _ => PAWN,
+        };
+        let prom_type = match chars.last() {
+            Some(c) if moving_pt == PAWN && c.is_ascii_alphabetic() && chars.len() > 2 => {
+                let pt = PieceType::try_from(c).map_err(|_| MoveError::InvalidPromotion(*c))?;
+                if pt == PAWN || pt == KING {
+                    return Err(MoveError::InvalidPromotion(*c));
+                }
+                chars.pop();
+                Some(pt)
+            }
+            _ => None,
+        };
+        if chars.len() < 2 || chars.len() > 4 {
+            return Err(illegal());
+        }
+
+        let (from_chars, to_chars) = chars.split_at(chars.len() - 2);
+        let to_sq = parse_square(to_chars).ok_or_else(|| MoveError::InvalidSquare(to_chars.iter().collect()))?;
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in from_chars.iter() {
+            if let Ok(file) = File::try_from(c) {
+                from_file = Some(file);
+            } else if let Ok(rank) = Rank::try_from(c) {
+                from_rank = Some(rank);
+            } else {
+                return Err(illegal());
+            }
+        }
+
+        let to_sq = self.orient(&to_sq);
+        let moves = self.legal_moves();
+        let mut candidates = moves.iter().copied().filter(|m| {
+            let from_sq = self.orient(&m.from_square());
+            let is_promotion = m.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP);
+            m.to_square() == to_sq
+                && !m.is_move_type(MOVE_CASTLING)
+                && self.piece_type_on(&m.from_square()) == moving_pt
+                && (from_file.is_none() || from_file == Some(from_sq.file()))
+                && (from_rank.is_none() || from_rank == Some(from_sq.rank()))
+                && prom_type.map_or(!is_promotion, |pt| is_promotion && m.promotion_type() == pt)
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(m), None) => Ok(m),
+            (Some(_), Some(_)) => Err(MoveError::AmbiguousMove(String::from(san))),
+            _ => Err(illegal()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(fen: &str, name: &str) -> Result<Move, MoveError> {
+        Position::from(fen).parse_uci_move(name)
+    }
+
+    #[test]
+    fn move_flags_come_from_the_board() {
+        let m = parse(INITIAL_FEN, "e2e4").unwrap();
+        assert!(m.is_move_type(MOVE_DOUBLE_PUSH));
+        assert!(parse(INITIAL_FEN, "g1f3").unwrap().is_move_type(MOVE_NORMAL));
+
+        let m = parse("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1").unwrap();
+        assert!(m.is_move_type(MOVE_CASTLING));
+
+        let m = parse("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6").unwrap();
+        assert!(m.is_move_type(MOVE_ENPASSANT));
+
+        let m = parse("1r2k3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7b8n").unwrap();
+        assert!(m.is_move_type(MOVE_PROM_CAP));
+        assert!(m.promotion_type() == KNIGHT);
+        assert!(m.capture_type() == ROOK);
+    }
+
+    #[test]
+    fn black_moves_use_real_squares() {
+        let pos = Position::from("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let m = pos.parse_uci_move("e7e5").unwrap();
+        // Stored from black's point of view
+        assert_eq!(String::from(&m.from_square()), "e2");
+        assert_eq!(pos.move_to_uci(&m), "e7e5");
+
+        let pos = Position::from("4k3/8/8/8/8/8/p7/4K3 b - - 0 1");
+        let m = pos.parse_uci_move("a2a1q").unwrap();
+        assert_eq!(pos.move_to_uci(&m), "a2a1q");
+    }
+
+    #[test]
+    fn every_legal_move_round_trips() {
+        let pos = Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        for m in pos.legal_moves().iter() {
+            assert_eq!(pos.parse_uci_move(&pos.move_to_uci(m)), Ok(*m));
+        }
+    }
+
+    // Placement, side to move, castling and en-passant fields of the position after m
+    fn fen_after(pos: &Position, m: &Move) -> String {
+        pos.make_move(m).to_fen().split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn castling_in_standard_chess() {
+        let pos = Position::from("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let king_side = pos.parse_uci_move("e1g1").unwrap();
+        assert_eq!(pos.parse_uci_move("e1h1"), Ok(king_side));
+        assert_eq!(pos.move_to_uci(&king_side), "e1g1");
+        assert_eq!(fen_after(&pos, &king_side), "r3k2r/8/8/8/8/8/8/R4RK1 b kq -");
+    }
+
+    #[test]
+    fn castling_with_chess960_set() {
+        let mut pos = Position::from("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        pos.set_chess960(true);
+        let queen_side = pos.parse_uci_move("e1a1").unwrap();
+        assert_eq!(pos.move_to_uci(&queen_side), "e1a1");
+        assert_eq!(pos.parse_uci_move("e1c1"), Err(MoveError::IllegalMove(String::from("e1c1"))));
+        assert_eq!(fen_after(&pos, &queen_side), "r3k2r/8/8/8/8/8/8/2KR3R b ha -");
+    }
+
+    #[test]
+    fn castling_in_chess960() {
+        // The king takes its own rook on g1 and lands on g1, the rook goes to f1
+        let pos = Position::from("1r2k1r1/6p1/8/8/8/8/6P1/1R2K1R1 w GBgb - 0 1");
+        let king_side = pos.parse_uci_move("e1g1").unwrap();
+        assert!(king_side.is_move_type(MOVE_CASTLING));
+        assert_eq!(fen_after(&pos, &king_side), "1r2k1r1/6p1/8/8/8/8/6P1/1R3RK1 b gb -");
+
+        let pos = Position::from("1r2k1r1/6p1/8/8/8/8/6P1/1R2K1R1 b GBgb - 0 1");
+        let queen_side = pos.parse_uci_move("e8b8").unwrap();
+        assert_eq!(pos.move_to_uci(&queen_side), "e8b8");
+        assert_eq!(fen_after(&pos, &queen_side), "2kr2r1/6p1/8/8/8/8/6P1/1R2K1R1 w GB -");
+    }
+
+    #[test]
+    fn invalid_moves() {
+        assert_eq!(parse(INITIAL_FEN, "e2e"), Err(MoveError::IllegalMove(String::from("e2e"))));
+        assert_eq!(parse(INITIAL_FEN, "z2e4"), Err(MoveError::InvalidSquare(String::from("z2"))));
+        assert_eq!(parse(INITIAL_FEN, "e2e9"), Err(MoveError::InvalidSquare(String::from("e9"))));
+        assert_eq!(parse("8/4P3/8/8/k7/8/8/7K w - - 0 1", "e7e8k"), Err(MoveError::InvalidPromotion('k')));
+        assert_eq!(parse(INITIAL_FEN, "e4e5"), Err(MoveError::NoPieceOnSquare(String::from("e4"))));
+        assert_eq!(parse(INITIAL_FEN, "e7e5"), Err(MoveError::NoPieceOnSquare(String::from("e7"))));
+        assert_eq!(parse(INITIAL_FEN, "e2e5"), Err(MoveError::IllegalMove(String::from("e2e5"))));
+        assert_eq!(parse(INITIAL_FEN, "e1g1"), Err(MoveError::IllegalMove(String::from("e1g1"))));
+    }
+
+    fn san(fen: &str, uci: &str) -> String {
+        let pos = Position::from(fen);
+        let m = pos.parse_uci_move(uci).unwrap();
+        let san = pos.move_to_san(&m);
+        assert_eq!(pos.parse_san(&san), Ok(m));
+        san
+    }
+
+    #[test]
+    fn disambiguation_by_file() {
+        assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2"), "Nbd2");
+    }
+
+    #[test]
+    fn disambiguation_by_rank() {
+        assert_eq!(san("4k3/8/8/8/8/5N2/8/4KN2 w - - 0 1", "f1d2"), "N1d2");
+    }
+
+    #[test]
+    fn disambiguation_by_file_and_rank() {
+        assert_eq!(san("4k3/8/8/8/8/1N6/8/1N2KN2 w - - 0 1", "b1d2"), "Nb1d2");
+    }
+
+    #[test]
+    fn enpassant() {
+        assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), "exd6");
+        assert_eq!(san("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", "d4e3"), "dxe3");
+    }
+
+    #[test]
+    fn promotion_with_check() {
+        assert_eq!(san("8/4P3/8/8/k7/8/8/7K w - - 0 1", "e7e8q"), "e8=Q+");
+        assert_eq!(san("3r4/4P3/8/8/k7/8/8/7K w - - 0 1", "e7d8n"), "exd8=N");
+    }
+
+    #[test]
+    fn capture_with_mate() {
+        assert_eq!(san("3r2k1/8/8/8/8/8/5PPP/r2R2K1 b - - 0 1", "a1d1"), "Raxd1#");
+        assert_eq!(san("3r2k1/8/8/8/8/8/5PPP/r2R2K1 b - - 0 1", "d8d1"), "Rdxd1#");
+    }
+
+    #[test]
+    fn castling_for_black() {
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8"), "O-O-O");
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8g8"), "O-O");
+        let pos = Position::from("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        assert_eq!(pos.parse_san("0-0-0"), pos.parse_uci_move("e8c8"));
+    }
+
+    #[test]
+    fn tolerant_parsing() {
+        let pos = Position::from("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        assert_eq!(pos.parse_san("Nb1d2"), pos.parse_uci_move("b1d2"));
+        let pos = Position::from("8/4P3/8/8/k7/8/8/7K w - - 0 1");
+        assert_eq!(pos.parse_san("e8Q+!"), pos.parse_uci_move("e7e8q"));
+    }
+
+    #[test]
+    fn ambiguous_move_is_rejected() {
+        let pos = Position::from("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(pos.parse_san("Rd1"), Err(MoveError::AmbiguousMove(String::from("Rd1"))));
+        assert_eq!(pos.parse_san("Rad1"), pos.parse_uci_move("a1d1"));
+        let pos = Position::from("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        assert_eq!(pos.parse_san("N1d2"), Err(MoveError::AmbiguousMove(String::from("N1d2"))));
+    }
+
+    #[test]
+    fn illegal_move_is_rejected() {
+        let pos = Position::from("4k3/8/8/8/8/8/8/R3K2R w - - 0 1");
+        assert_eq!(pos.parse_san("O-O"), Err(MoveError::IllegalMove(String::from("O-O"))));
+        assert_eq!(pos.parse_san("Nf3"), Err(MoveError::IllegalMove(String::from("Nf3"))));
+    }
+}
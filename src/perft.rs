@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use crate::constants::*;
+use crate::position::Position;
+
+// Well-known node counts from the Chess Programming Wiki perft results page
+const PERFT_SUITE: [(&str, u32, u64); 12] = [
+    (INITIAL_FEN, 5, 4_865_609),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4, 4_085_603),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674_624),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 4, 422_333),
+    ("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", 4, 422_333),
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 4, 2_103_487),
+    ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 4, 3_894_594),
+    // Chess960
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 5, 8_146_062),
+    ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 4, 667_366),
+    ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", 5, 6_417_013),
+    ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 4, 382_958),
+    ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", 4, 1_171_749),
+];
+
+impl Position {
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        self.legal_moves().iter().map(|m| self.make_move(m).perft(depth - 1)).sum()
+    }
+
+    // Same count as perft but playing pseudo-legal moves in place, a move that
+    // leaves our king attacked is taken back without being counted. Neither
+    // version counts the last ply in bulk so both visit the same leaves.
+    pub fn perft_unmake(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        for m in self.pseudo_legal_moves().iter() {
+            #[cfg(debug_assertions)]
+            let before = *self;
+            let undo = self.do_move(m);
+            // The board is flipped, so our king now belongs to THEM
+            if !self.is_square_attacked(&self.king_square(&THEM), &US) {
+                nodes += self.perft_unmake(depth - 1);
+            }
+            self.undo_move(m, &undo);
+            #[cfg(debug_assertions)]
+            debug_assert!(*self == before);
+        }
+        nodes
+    }
+
+    // Prints the perft node count below every root move followed by the total
+    pub fn divide(&self, depth: u32) -> u64 {
+        let mut total = 0;
+        for m in self.legal_moves().iter() {
+            let nodes = if depth > 1 { self.make_move(m).perft(depth - 1) } else { 1 };
+            println!("{}: {}", self.move_to_uci(m), nodes);
+            total += nodes;
+        }
+        println!();
+        println!("Nodes: {}", total);
+        total
+    }
+}
+
+// Checks that every legal move within depth parses back from its UCI and SAN names
+fn notation_round_trip(pos: &Position, depth: u32) -> bool {
+    pos.legal_moves().iter().all(|m| {
+        let uci = pos.move_to_uci(m);
+        let san = pos.move_to_san(m);
+        if pos.parse_uci_move(&uci) != Ok(*m) || pos.parse_san(&san) != Ok(*m) {
+            println!("Notation round trip failed for {} ({}) in {}", uci, san, pos);
+            return false;
+        }
+        depth <= 1 || notation_round_trip(&pos.make_move(m), depth - 1)
+    })
+}
+
+// Runs every position of the regression suite and reports whether all of them passed
+pub fn run_suite() -> bool {
+    let mut all_passed = true;
+    for (fen, depth, expected) in PERFT_SUITE.iter() {
+        let start = Instant::now();
+        let pos = Position::from(*fen);
+        let nodes = pos.perft(*depth);
+        let elapsed = start.elapsed().as_millis();
+        let passed = nodes == *expected && pos.is_valid() && pos.to_fen() == *fen && notation_round_trip(&pos, 2);
+        all_passed &= passed;
+        println!(
+            "{} depth {} nodes {} expected {} time {}ms {}",
+            fen,
+            depth,
+            nodes,
+            expected,
+            elapsed,
+            if passed { "ok" } else { "FAILED" }
+        );
+    }
+    all_passed
+}
+
+fn nps(nodes: u64, elapsed: Duration) -> u128 {
+    nodes as u128 * 1000 / elapsed.as_millis().max(1)
+}
+
+// Runs the suite once with copy-make and once with make-unmake and reports the
+// speed of both, returns false if their node counts disagree
+pub fn compare_make_strategies() -> bool {
+    let mut all_matched = true;
+    let mut copy_total = (0, Duration::ZERO);
+    let mut unmake_total = (0, Duration::ZERO);
+    for (fen, depth, _) in PERFT_SUITE.iter() {
+        let mut pos = Position::from(*fen);
+
+        let start = Instant::now();
+        let copy_nodes = pos.perft(*depth);
+        let copy_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let unmake_nodes = pos.perft_unmake(*depth);
+        let unmake_elapsed = start.elapsed();
+
+        all_matched &= copy_nodes == unmake_nodes;
+        copy_total = (copy_total.0 + copy_nodes, copy_total.1 + copy_elapsed);
+        unmake_total = (unmake_total.0 + unmake_nodes, unmake_total.1 + unmake_elapsed);
+        println!(
+            "{} depth {} copy-make {} nps make-unmake {} nps{}",
+            fen,
+            depth,
+            nps(copy_nodes, copy_elapsed),
+            nps(unmake_nodes, unmake_elapsed),
+            if copy_nodes == unmake_nodes { "" } else { " MISMATCH" }
+        );
+    }
+    println!(
+        "Total copy-make {} nps make-unmake {} nps",
+        nps(copy_total.0, copy_total.1),
+        nps(unmake_total.0, unmake_total.1)
+    );
+    all_matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reduced depths of the suite so the tests stay fast in debug builds
+    fn assert_perft(fen: &str, depth: u32, expected: u64) {
+        assert_eq!(Position::from(fen).perft(depth), expected, "perft {} of {}", depth, fen);
+    }
+
+    #[test]
+    fn initial_position() {
+        assert_perft(INITIAL_FEN, 4, 197_281);
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3, 97_862);
+    }
+
+    #[test]
+    fn cpw_position_3() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43_238);
+    }
+
+    #[test]
+    fn cpw_position_4() {
+        assert_perft("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 3, 9_467);
+        assert_perft("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", 3, 9_467);
+    }
+
+    #[test]
+    fn cpw_position_5() {
+        assert_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3, 62_379);
+    }
+
+    #[test]
+    fn cpw_position_6() {
+        assert_perft("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 3, 89_890);
+    }
+
+    #[test]
+    fn chess960_positions() {
+        let fens = [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 3, 12_189),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 3, 18_002),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", 3, 10_471),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 4, 382_958),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", 3, 31_058),
+        ];
+        for (fen, depth, expected) in fens.iter() {
+            assert!(Position::from(*fen).is_chess960(), "{} should be detected as Chess960", fen);
+            assert_perft(fen, *depth, *expected);
+        }
+    }
+
+    #[test]
+    fn make_unmake_matches_copy_make() {
+        for (fen, _, _) in PERFT_SUITE.iter() {
+            let mut pos = Position::from(*fen);
+            assert_eq!(pos.perft_unmake(3), pos.perft(3), "perft 3 of {}", fen);
+            assert!(pos == Position::from(*fen));
+        }
+    }
+
+    #[test]
+    fn divide_matches_perft() {
+        let pos = Position::from(INITIAL_FEN);
+        assert_eq!(pos.divide(3), pos.perft(3));
+    }
+}
@@ -0,0 +1,503 @@
+use std::fmt;
+
+use crate::constants::*;
+use crate::position::Position;
+use crate::types::{GameResult, Move, PgnError};
+
+const LINE_WIDTH: usize = 80;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    San(String),
+}
+
+#[derive(Clone)]
+pub struct PgnMove {
+    pub m: Move,
+    pub nags: Vec<u8>,
+    // Comment following the move
+    pub comment: Option<String>,
+    // Alternatives to this move, played from the position before it
+    pub variations: Vec<Variation>,
+}
+
+#[derive(Clone, Default)]
+pub struct Variation {
+    // Comment preceding the first move
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: Position,
+    pub main_line: Variation,
+    pub result: GameResult,
+}
+
+impl PgnMove {
+    pub fn new(m: Move) -> PgnMove {
+        PgnMove {
+            m,
+            nags: Vec::new(),
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_pgn())
+    }
+}
+
+impl PgnGame {
+    // A game without moves, the FEN tags are only added for non-standard starts
+    pub fn new(start: Position) -> PgnGame {
+        let mut game = PgnGame {
+            tags: Vec::new(),
+            start,
+            main_line: Variation::default(),
+            result: GameResult::Unknown,
+        };
+        let fen = start.to_fen();
+        if fen != INITIAL_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old_value)) => *old_value = String::from(value),
+            None => self.tags.push((String::from(name), String::from(value))),
+        }
+    }
+
+    // Position reached at the end of the main line
+    pub fn final_position(&self) -> Position {
+        self.main_line.moves.iter().fold(self.start, |pos, pgn_move| pos.make_move(&pgn_move.m))
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in self.tags.iter() {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        if !self.tags.is_empty() {
+            pgn.push('\n');
+        }
+
+        let mut words = Vec::new();
+        write_variation(&self.start, self.start.fullmoves(), &self.main_line, &mut words);
+        words.push(self.result.to_string());
+
+        let mut line_len = 0;
+        for word in words.iter() {
+            if line_len > 0 && line_len + 1 + word.len() > LINE_WIDTH {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            pgn.push_str(word);
+            line_len += word.len();
+        }
+        pgn.push('\n');
+        pgn
+    }
+}
+
+// Black moves get their number repeated at the start of a line and after
+// anything that interrupts the movetext
+fn write_variation(pos: &Position, fullmoves: u32, variation: &Variation, words: &mut Vec<String>) {
+    if let Some(comment) = &variation.comment {
+        words.push(format!("{{{}}}", comment));
+    }
+    let mut pos = *pos;
+    let mut fullmoves = fullmoves;
+    let mut needs_number = true;
+    for pgn_move in variation.moves.iter() {
+        if pos.side_to_move() == WHITE {
+            words.push(format!("{}.", fullmoves));
+        } else if needs_number {
+            words.push(format!("{}...", fullmoves));
+        }
+        words.push(pos.move_to_san(&pgn_move.m));
+        for nag in pgn_move.nags.iter() {
+            words.push(format!("${}", nag));
+        }
+        if let Some(comment) = &pgn_move.comment {
+            words.push(format!("{{{}}}", comment));
+        }
+        for sub_variation in pgn_move.variations.iter() {
+            let first = words.len();
+            write_variation(&pos, fullmoves, sub_variation, words);
+            if words.len() == first {
+                words.push(String::from("()"));
+            } else {
+                words[first].insert(0, '(');
+                words.last_mut().unwrap().push(')');
+            }
+        }
+        needs_number = pgn_move.comment.is_some() || !pgn_move.variations.is_empty();
+
+        if pos.side_to_move() == BLACK {
+            fullmoves += 1;
+        }
+        pos = pos.make_move(&pgn_move.m);
+    }
+}
+
+// Move suffix annotations and their numeric annotation glyph equivalents
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+#=:-/".contains(c)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            // Escaped lines are ignored entirely
+            '%' if line_start => {
+                chars.by_ref().find(|c| *c == '\n');
+                line_start = true;
+                continue;
+            }
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                tokens.push(Token::Comment(String::from(comment.trim())));
+                line_start = true;
+                continue;
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::UnterminatedComment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            }
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '*' => tokens.push(Token::Result(GameResult::Unknown)),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(*c);
+                    chars.next();
+                }
+                if let Ok(nag) = digits.parse::<u8>() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            '!' | '?' => {
+                let mut suffix = c.to_string();
+                while let Some(c) = chars.peek().filter(|c| **c == '!' || **c == '?') {
+                    suffix.push(*c);
+                    chars.next();
+                }
+                if let Some(nag) = suffix_nag(&suffix) {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            c if is_symbol_char(c) => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.peek().filter(|c| is_symbol_char(**c)) {
+                    symbol.push(*c);
+                    chars.next();
+                }
+                match symbol.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" => tokens.push(Token::Result(GameResult::from(symbol.as_str()))),
+                    // Move numbers carry no information, the dots are skipped as separators
+                    _ if symbol.chars().all(|c| c.is_ascii_digit()) => {}
+                    _ => tokens.push(Token::San(symbol)),
+                }
+            }
+            _ => {}
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+// [Name "value"] with backslash escapes inside the value
+fn read_tag(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<Token, PgnError> {
+    let mut raw = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut name = String::new();
+    let mut value = String::new();
+    loop {
+        let c = chars.next().ok_or(PgnError::UnterminatedTag)?;
+        if c == '\n' && !in_string {
+            continue;
+        }
+        raw.push(c);
+        if in_string {
+            if escaped {
+                value.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            } else {
+                value.push(c);
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ']' {
+            break;
+        } else if !c.is_whitespace() {
+            name.push(c);
+        }
+    }
+    if name.is_empty() || !name.chars().all(is_symbol_char) {
+        return Err(PgnError::InvalidTag(raw));
+    }
+    Ok(Token::Tag(name, value))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    idx: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx)
+    }
+
+    fn game(&mut self) -> Result<PgnGame, PgnError> {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(name, value)) = self.peek() {
+            tags.push((name.clone(), value.clone()));
+            self.idx += 1;
+        }
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Position::from_fen(fen).map_err(PgnError::InvalidFen)?,
+            None => Position::from(INITIAL_FEN),
+        };
+        // Move generation assumes a sane board, untrusted files must not reach it otherwise
+        let violations = start.validate();
+        if !violations.is_empty() {
+            return Err(PgnError::InvalidPosition(violations));
+        }
+        let mut game = PgnGame {
+            tags,
+            start,
+            main_line: Variation::default(),
+            result: GameResult::Unknown,
+        };
+        game.main_line = self.variation(&start, false)?;
+        if let Some(Token::Result(result)) = self.peek() {
+            game.result = *result;
+            self.idx += 1;
+        } else if let Some(result) = game.tag("Result") {
+            game.result = GameResult::from(result);
+        }
+        Ok(game)
+    }
+
+    // Reads moves until the end of the variation, the game result or the tags
+    // of the next game
+    fn variation(&mut self, start: &Position, nested: bool) -> Result<Variation, PgnError> {
+        let mut variation = Variation::default();
+        let mut pos = *start;
+        let mut prev_pos = *start;
+        loop {
+            let token = match self.peek() {
+                Some(token) => token.clone(),
+                None if nested => return Err(PgnError::UnterminatedVariation),
+                None => return Ok(variation),
+            };
+            match token {
+                Token::Tag(_, _) | Token::Result(_) if nested => return Err(PgnError::UnterminatedVariation),
+                Token::Tag(_, _) | Token::Result(_) => return Ok(variation),
+                Token::VariationEnd if nested => {
+                    self.idx += 1;
+                    return Ok(variation);
+                }
+                Token::VariationEnd => return Err(PgnError::UnexpectedVariationEnd),
+                Token::VariationStart => {
+                    self.idx += 1;
+                    let sub_variation = self.variation(&prev_pos, true)?;
+                    match variation.moves.last_mut() {
+                        Some(pgn_move) => pgn_move.variations.push(sub_variation),
+                        None => return Err(PgnError::VariationWithoutMove),
+                    }
+                }
+                Token::Comment(comment) => {
+                    self.idx += 1;
+                    let target = match variation.moves.last_mut() {
+                        Some(pgn_move) => &mut pgn_move.comment,
+                        None => &mut variation.comment,
+                    };
+                    *target = match target.take() {
+                        Some(old) => Some(format!("{} {}", old, comment)),
+                        None => Some(comment),
+                    };
+                }
+                Token::Nag(nag) => {
+                    self.idx += 1;
+                    if let Some(pgn_move) = variation.moves.last_mut() {
+                        pgn_move.nags.push(nag);
+                    }
+                }
+                Token::San(san) => {
+                    self.idx += 1;
+                    let m = pos.parse_san(&san).map_err(|err| PgnError::InvalidMove(san.clone(), err))?;
+                    variation.moves.push(PgnMove::new(m));
+                    prev_pos = pos;
+                    pos = pos.make_move(&m);
+                }
+            }
+        }
+    }
+}
+
+// Parses every game in the text, games may start from INITIAL_FEN or a FEN tag
+pub fn parse(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        idx: 0,
+    };
+    let mut games = Vec::new();
+    while parser.peek().is_some() {
+        games.push(parser.game()?);
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Square, Violation};
+    use std::convert::TryFrom;
+
+    fn parse_error(text: &str) -> Option<PgnError> {
+        parse(text).err()
+    }
+
+    #[test]
+    fn fen_tag_without_kings() {
+        assert_eq!(
+            parse_error("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n1. Kd2 *"),
+            Some(PgnError::InvalidPosition(vec![Violation::MissingKing(WHITE), Violation::MissingKing(BLACK)]))
+        );
+        assert_eq!(
+            parse_error("[FEN \"4k3/8/8/8/8/8/8/8 w - - 0 1\"]\n\n1. Kd2 *"),
+            Some(PgnError::InvalidPosition(vec![Violation::MissingKing(WHITE)]))
+        );
+    }
+
+    #[test]
+    fn fen_tag_with_enpassant_square_without_pawn() {
+        assert_eq!(
+            parse_error("[FEN \"4k3/8/8/8/3p4/8/8/4K3 b - e3 0 1\"]\n\n1... dxe3 *"),
+            Some(PgnError::InvalidPosition(vec![Violation::InvalidEnpassantSquare(Square::try_from("e3").unwrap())]))
+        );
+    }
+
+    #[test]
+    fn valid_fen_tag() {
+        let games = parse("[FEN \"4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1\"]\n\n1... dxe3 *").unwrap();
+        let fen = games[0].final_position().to_fen();
+        assert_eq!(fen.split_whitespace().next(), Some("4k3/8/8/8/8/4p3/8/4K3"));
+    }
+
+    const ANNOTATED: &str = "% escaped line, ignored\n\
+    [Event \"Test \\\"quoted\\\"\"]\n\
+    [Result \"1-0\"]\n\
+    \n\
+    {Opening} 1. e4 e5!? 2. Nf3 (2. f4 exf4 (2... d5 3. exd5) 3. Nf3) Nc6 ; rest of line\n\
+    3. Bb5 $1 {Ruy Lopez} a6 4. Ba4?! 1-0\n";
+
+    const ANNOTATED_OUTPUT: &str = "[Event \"Test \\\"quoted\\\"\"]\n\
+    [Result \"1-0\"]\n\
+    \n\
+    {Opening} 1. e4 e5 $5 2. Nf3 (2. f4 exf4 (2... d5 3. exd5) 3. Nf3) 2... Nc6\n\
+    {rest of line} 3. Bb5 $1 {Ruy Lopez} 3... a6 4. Ba4 $6 1-0\n";
+
+    #[test]
+    fn write_annotated_game() {
+        let games = parse(ANNOTATED).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].tag("Event"), Some("Test \"quoted\""));
+        assert_eq!(games[0].to_pgn(), ANNOTATED_OUTPUT);
+    }
+
+    #[test]
+    fn written_game_parses_back() {
+        let games = parse(ANNOTATED_OUTPUT).unwrap();
+        assert_eq!(games[0].to_pgn(), ANNOTATED_OUTPUT);
+    }
+
+    #[test]
+    fn nested_variations() {
+        let game = &parse(ANNOTATED).unwrap()[0];
+        let main_line = &game.main_line.moves;
+        assert_eq!(main_line.len(), 7);
+        assert_eq!(main_line[1].nags, vec![5]);
+        assert_eq!(main_line[2].variations.len(), 1);
+        let variation = &main_line[2].variations[0].moves;
+        assert_eq!(variation.len(), 3);
+        assert_eq!(variation[1].variations.len(), 1);
+        assert_eq!(variation[1].variations[0].moves.len(), 2);
+        assert_eq!(main_line[3].comment.as_deref(), Some("rest of line"));
+    }
+
+    #[test]
+    fn percent_escape_line() {
+        let games = parse("1. e4\n%1... d5\ne5 *").unwrap();
+        assert_eq!(games[0].to_pgn(), "1. e4 e5 *\n");
+        let games = parse("1. e4\n%1... d5\n%2. d4\ne5 *").unwrap();
+        assert_eq!(games[0].to_pgn(), "1. e4 e5 *\n");
+    }
+
+    #[test]
+    fn unbalanced_variations() {
+        assert_eq!(parse_error("1. e4 (1. d4 *"), Some(PgnError::UnterminatedVariation));
+        assert_eq!(parse_error("1. e4 ) *"), Some(PgnError::UnexpectedVariationEnd));
+        assert_eq!(parse_error("(1. d4) 1. e4 *"), Some(PgnError::VariationWithoutMove));
+    }
+}
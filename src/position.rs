@@ -1,81 +1,261 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::attacks::{attacks, between, pawn_attacks};
 use crate::constants::*;
 use crate::type_traits::Printable;
-use crate::types::{Bitboard, CastlingRights, Color, ColoredPiece, FenStage, Move, PieceType, Square};
+use crate::types::{Bitboard, CastlingRights, Color, ColoredPiece, FenError, FenStage, File, Move, PieceType, Rank, Square};
+use crate::zobrist;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Eq, PartialEq)]
 pub struct Position {
     piece_types: [Bitboard; 6],
     colors: [Bitboard; 2],
+    // Redundant mailbox of the real board with real colors, so that flipping
+    // does not have to touch it
+    board: [ColoredPiece; 64],
     enpassant_sq: Square,
     castling_rights: CastlingRights,
+    // Rook square for every castling right, indexed by the right's bit
+    castling_rooks: [Square; 4],
     side_to_move: Color,
     halfmoves: u8,
     fullmoves: u32,
+    hash: u64,
+    chess960: bool,
+}
+
+// State do_move cannot recover from the move itself
+#[derive(Copy, Clone)]
+pub struct Undo {
+    castling_rights: CastlingRights,
+    castling_rooks: [Square; 4],
+    enpassant_sq: Square,
+    halfmoves: u8,
+    hash: u64,
+    chess960: bool,
 }
 
+// Panics on malformed input, untrusted FENs should go through Position::from_fen
 impl From<&String> for Position {
     fn from(fen: &String) -> Self {
+        Position::from(fen.as_str())
+    }
+}
+
+impl From<&str> for Position {
+    fn from(fen: &str) -> Self {
+        match Position::from_fen(fen) {
+            Ok(position) => position,
+            Err(err) => panic!("Invalid FEN '{}': {}", fen, err),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_fen())
+    }
+}
+
+// King and rook destinations for the king on king_sq castling with the rook on
+// rook_sq, the same as in standard chess whatever the start files
+pub fn castling_destinations(king_sq: &Square, rook_sq: &Square) -> (Square, Square) {
+    if rook_sq.0 > king_sq.0 {
+        (G1, F1)
+    } else {
+        (C1, D1)
+    }
+}
+
+impl Position {
+    pub fn from_fen(fen: &str) -> Result<Position, FenError> {
         let mut position = Position {
             piece_types: [Bitboard(0); 6],
             colors: [Bitboard(0); 2],
+            board: [NO_PIECE; 64],
             enpassant_sq: SQUARE_INVALID,
             castling_rights: CASTLING_RIGHT_NONE,
+            castling_rooks: DEFAULT_CASTLING_ROOKS,
             side_to_move: WHITE,
             halfmoves: 0,
             fullmoves: 1,
+            hash: 0,
+            chess960: false,
         };
         let mut fen_stages = FEN_STAGES.iter();
         let mut stage = fen_stages.next().unwrap();
-        let mut curr_square = A8;
-        let fen_parts: Vec<String> = fen.split(" ").map(|x| String::from(x)).collect();
-        for fen_part in fen_parts.iter() {
-            if *stage == FenStage::Pieces {
-                for ch in fen_part.chars() {
-                    if ch.is_numeric() {
-                        curr_square += Square(ch.to_digit(10).unwrap() as u8);
-                    } else if ch == '/' {
-                        curr_square -= Square(16);
-                    } else {
-                        let ColoredPiece(piece_type, color) = ColoredPiece::from(&ch);
-                        position.piece_types[piece_type.0 as usize] |= Bitboard::from(&curr_square);
-                        position.colors[color.0 as usize] |= Bitboard::from(&curr_square);
-                        curr_square += Square(1);
+        let mut side_to_move = WHITE;
+        for fen_part in fen.split_whitespace() {
+            match *stage {
+                FenStage::Pieces => position.parse_pieces(fen_part)?,
+                FenStage::SideToMove => side_to_move = Color::try_from(fen_part)?,
+                FenStage::CastlingRights => position.parse_castling(fen_part)?,
+                FenStage::EnpassantSquare => {
+                    let sq = Square::try_from(fen_part)?;
+                    if sq != SQUARE_INVALID && sq.rank() != RANK_3 && sq.rank() != RANK_6 {
+                        return Err(FenError::InvalidEnpassantSquare(String::from(fen_part)));
                     }
+                    position.enpassant_sq = sq;
                 }
-            } else if *stage == FenStage::SideToMove {
-                let ch = fen_part.chars().next().unwrap();
-                position.side_to_move = Color::from(&ch);
-            } else if *stage == FenStage::CastlingRights {
-                for ch in fen_part.chars() {
-                    position.castling_rights ^= CastlingRights::from(&ch);
+                FenStage::HalfMoves => {
+                    position.halfmoves = fen_part
+                        .parse::<u8>()
+                        .map_err(|_| FenError::InvalidHalfmoves(String::from(fen_part)))?;
                 }
-            } else if *stage == FenStage::EnpassantSquare {
-                position.enpassant_sq = Square::from(fen_part);
-            } else if *stage == FenStage::HalfMoves {
-                position.halfmoves = fen_part.parse::<u8>().unwrap();
-            } else if *stage == FenStage::FullMoves {
-                position.fullmoves = fen_part.parse::<u32>().unwrap();
+                FenStage::FullMoves => {
+                    position.fullmoves = fen_part
+                        .parse::<u32>()
+                        .map_err(|_| FenError::InvalidFullmoves(String::from(fen_part)))?;
+                }
+                FenStage::Done => break,
             }
             stage = fen_stages.next().unwrap();
         }
 
-        if position.side_to_move == BLACK {
+        // The move counters are optional, everything before them is not
+        if *stage <= FenStage::EnpassantSquare {
+            return Err(FenError::MissingField(*stage));
+        }
+
+        // Flipping also hands the move over to black
+        if side_to_move == BLACK {
             position.flip();
         }
+        position.hash = position.compute_hash();
+        debug_assert!(position.mailbox_agrees());
+
+        Ok(position)
+    }
 
-        debug_assert!(*stage > FenStage::EnpassantSquare);
+    fn parse_pieces(&mut self, pieces: &str) -> Result<(), FenError> {
+        let mut rank = RANK_8;
+        let mut file = 0;
+        for ch in pieces.chars() {
+            if ch == '/' {
+                if file != 8 {
+                    return Err(FenError::InvalidRankLength(rank));
+                }
+                if rank == RANK_1 {
+                    return Err(FenError::TooManyRanks);
+                }
+                rank = Rank(rank.0 - 1);
+                file = 0;
+            } else if let Some(empty) = ch.to_digit(10) {
+                if empty == 0 || file + empty > 8 {
+                    return Err(FenError::InvalidRankLength(rank));
+                }
+                file += empty;
+            } else {
+                let ColoredPiece(piece_type, color) = ColoredPiece::try_from(&ch)?;
+                if file >= 8 {
+                    return Err(FenError::InvalidRankLength(rank));
+                }
+                self.xor_piece(&Square::new(&File(file as u8), &rank), &piece_type, &color);
+                file += 1;
+            }
+        }
+        if file != 8 {
+            return Err(FenError::InvalidRankLength(rank));
+        }
+        if rank != RANK_1 {
+            return Err(FenError::TooFewRanks);
+        }
+        Ok(())
+    }
 
-        position
+    // Accepts KQkq, Shredder-FEN file letters and X-FEN, where K and Q name the
+    // outermost rook and a file letter is used for an inner one. Only file letters
+    // switch to Chess960, KQkq with pieces off their standard squares is left for
+    // validate to reject. Called on the real board before any flip.
+    fn parse_castling(&mut self, castling: &str) -> Result<(), FenError> {
+        if castling == "-" {
+            return Ok(());
+        }
+        for ch in castling.chars() {
+            let (color, rank, offset) = if ch.is_ascii_uppercase() { (WHITE, RANK_1, 0) } else { (BLACK, RANK_8, 2) };
+            let kings = self.pieces_of(&KING, &color) & RANK_BBS[rank.0 as usize];
+            let king_file = if kings.is_empty() { FILE_E } else { Square(kings.lsb() as u8).file() };
+            let mut rooks = self.pieces_of(&ROOK, &color) & RANK_BBS[rank.0 as usize];
+            let (idx, rook_sq) = match ch.to_ascii_lowercase() {
+                'k' => {
+                    let outermost = rooks.filter(|sq| sq.file().0 > king_file.0).last();
+                    (offset, outermost.unwrap_or(DEFAULT_CASTLING_ROOKS[offset]))
+                }
+                'q' => {
+                    let outermost = rooks.find(|sq| sq.file().0 < king_file.0);
+                    (offset + 1, outermost.unwrap_or(DEFAULT_CASTLING_ROOKS[offset + 1]))
+                }
+                c => {
+                    let file = File::try_from(&c).map_err(|_| FenError::InvalidCastlingRight(ch))?;
+                    let idx = if file.0 > king_file.0 { offset } else { offset + 1 };
+                    self.chess960 = true;
+                    (idx, Square::new(&file, &rank))
+                }
+            };
+            self.castling_rights |= CASTLING_RIGHTS[idx];
+            self.castling_rooks[idx] = rook_sq;
+        }
+        Ok(())
     }
-}
 
-impl From<&str> for Position {
-    fn from(fen: &str) -> Self {
-        Position::from(&String::from(fen))
+    // KQkq in standard chess and Shredder-FEN file letters in Chess960
+    fn castling_string(&self) -> String {
+        let mut castling = String::new();
+        for (idx, right) in CASTLING_RIGHTS.iter().enumerate() {
+            if !self.castling_rights.allows(*right) {
+                continue;
+            }
+            if self.chess960 {
+                let file_ch = char::from(&self.castling_rooks[idx].file());
+                castling.push(if idx < 2 { file_ch.to_ascii_uppercase() } else { file_ch });
+            } else {
+                castling.push(char::from(right));
+            }
+        }
+        if castling.is_empty() { String::from("-") } else { castling }
+    }
+
+    // Undoes the internal flip so the FEN always describes the real board
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_fen(&self) -> String {
+        let real_pos = if self.side_to_move == BLACK { self.flipped() } else { *self };
+        let mut fen = String::new();
+        for rank in RANKS.iter().rev() {
+            let mut empty = 0;
+            for file in FILES.iter() {
+                let sq = Square::new(file, rank);
+                let pt = real_pos.piece_type_on(&sq);
+                if pt == PIECE_NONE {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                let color = if real_pos.occupancy(&WHITE).contains(&sq) { WHITE } else { BLACK };
+                fen.push(char::from(&ColoredPiece(pt, color)));
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if *rank != RANK_1 {
+                fen.push('/');
+            }
+        }
+
+        format!(
+            "{} {} {} {} {} {}",
+            fen,
+            char::from(&self.side_to_move),
+            real_pos.castling_string(),
+            String::from(&real_pos.enpassant_sq),
+            self.halfmoves,
+            self.fullmoves
+        )
     }
-}
 
-impl Position {
     pub fn flip(&mut self) {
         for piece_type in PIECE_TYPES.iter() {
             self.piece_types[piece_type.0 as usize] =
@@ -95,93 +275,380 @@ impl Position {
         let tmp_cr = (self.castling_rights.0 & 3) << 2;
         self.castling_rights.0 >>= 2;
         self.castling_rights.0 ^= tmp_cr;
+        let rooks = self.castling_rooks;
+        self.castling_rooks = [rooks[2], rooks[3], rooks[0], rooks[1]];
+        for rook_sq in self.castling_rooks.iter_mut() {
+            rook_sq.0 ^= 56;
+        }
 
         self.side_to_move = !self.side_to_move;
+        self.hash ^= zobrist::side_key();
     }
 
     pub fn flipped(&self) -> Position {
-        let mut pos = self.clone();
+        let mut pos = *self;
         pos.flip();
         pos
     }
 
+    // Copy-make, the original position is left untouched
     pub fn make_move(&self, m: &Move) -> Position {
-        let mut pos = self.clone();
+        let mut pos = *self;
+        pos.do_move(m);
+        pos
+    }
+
+    // Plays the move in place and returns what undo_move needs to take it back
+    pub fn do_move(&mut self, m: &Move) -> Undo {
+        let undo = Undo {
+            castling_rights: self.castling_rights,
+            castling_rooks: self.castling_rooks,
+            enpassant_sq: self.enpassant_sq,
+            halfmoves: self.halfmoves,
+            hash: self.hash,
+            chess960: self.chess960,
+        };
         let from_sq = m.from_square();
         let to_sq = m.to_square();
-        let moving_pt = pos.piece_type_on(&from_sq);
+        let moving_pt = self.piece_type_on(&from_sq);
 
-        pos.castling_rights.spoil(CastlingRights(CASTLING_SPOILERS[from_sq.0 as usize]));
-        pos.castling_rights.spoil(CastlingRights(CASTLING_SPOILERS[to_sq.0 as usize]));
+        self.hash ^= self.state_key();
+        if moving_pt == KING {
+            self.spoil_castling(0);
+            self.spoil_castling(1);
+        }
+        for idx in 0..4 {
+            if self.castling_rooks[idx] == from_sq || self.castling_rooks[idx] == to_sq {
+                self.spoil_castling(idx);
+            }
+        }
 
+        self.halfmoves = self.halfmoves.saturating_add(1);
+        if self.side_to_move == BLACK {
+            self.fullmoves += 1;
+        }
         if moving_pt == PAWN {
-            pos.halfmoves = 0;
+            self.halfmoves = 0;
         }
 
         if m.is_move_type(MOVE_NORMAL) {
-            pos.move_piece(&from_sq, &to_sq, &moving_pt, &US);
+            self.move_piece(&from_sq, &to_sq, &moving_pt, &US);
         } else if m.is_move_type(MOVE_CAPTURE) {
-            let captured_pt = pos.piece_type_on(&to_sq);
-            pos.toggle_piece(&to_sq, &captured_pt, &THEM);
-            pos.move_piece(&from_sq, &to_sq, &moving_pt, &US);
-            pos.halfmoves = 0;
+            let captured_pt = self.piece_type_on(&to_sq);
+            self.toggle_piece(&to_sq, &captured_pt, &THEM);
+            self.move_piece(&from_sq, &to_sq, &moving_pt, &US);
+            self.halfmoves = 0;
         } else if m.is_move_type(MOVE_DOUBLE_PUSH) {
-            pos.move_piece(&from_sq, &to_sq, &moving_pt, &US);
-            pos.enpassant_sq = to_sq - Square(8);
+            self.move_piece(&from_sq, &to_sq, &moving_pt, &US);
+            self.enpassant_sq = to_sq - Square(8);
         } else if m.is_move_type(MOVE_ENPASSANT) {
-            pos.move_piece(&from_sq, &to_sq, &moving_pt, &US);
-            let ep_sq = pos.enpassant_sq;
-            pos.toggle_piece(&ep_sq, &PAWN, &THEM);
+            self.move_piece(&from_sq, &to_sq, &moving_pt, &US);
+            self.toggle_piece(&(to_sq - Square(8)), &PAWN, &THEM);
         } else if m.is_move_type(MOVE_CASTLING) {
-            let (rfrom_sq, rto_sq) = match to_sq {
-                C1 => (A1, D1),
-                G1 => (H1, F1),
-                _ => panic!("Unknown castling to square"),
-            };
-            pos.toggle_piece(&rfrom_sq, &ROOK, &US);
-            pos.toggle_piece(&from_sq, &KING, &US);
-            pos.toggle_piece(&rto_sq, &ROOK, &US);
-            pos.toggle_piece(&to_sq, &KING, &US);
+            // Castling is encoded as the king capturing its own rook
+            let (kto_sq, rto_sq) = castling_destinations(&from_sq, &to_sq);
+            debug_assert!(!self.in_check());
+            self.toggle_piece(&to_sq, &ROOK, &US);
+            self.toggle_piece(&from_sq, &KING, &US);
+            self.toggle_piece(&rto_sq, &ROOK, &US);
+            self.toggle_piece(&kto_sq, &KING, &US);
         } else if m.is_move_type(MOVE_PROM_CAP) {
-            let captured_pt = pos.piece_type_on(&to_sq);
+            let captured_pt = self.piece_type_on(&to_sq);
             let prom_type = m.promotion_type();
-            pos.toggle_piece(&to_sq, &captured_pt, &THEM);
-            pos.toggle_piece(&from_sq, &PAWN, &US);
-            pos.toggle_piece(&to_sq, &prom_type, &US);
+            self.toggle_piece(&to_sq, &captured_pt, &THEM);
+            self.toggle_piece(&from_sq, &PAWN, &US);
+            self.toggle_piece(&to_sq, &prom_type, &US);
         } else if m.is_move_type(MOVE_PROMOTION) {
             let prom_type = m.promotion_type();
-            pos.toggle_piece(&from_sq, &PAWN, &US);
-            pos.toggle_piece(&to_sq, &prom_type, &US);
+            self.toggle_piece(&from_sq, &PAWN, &US);
+            self.toggle_piece(&to_sq, &prom_type, &US);
         }
 
         if !m.is_move_type(MOVE_DOUBLE_PUSH) {
-            pos.enpassant_sq = SQUARE_INVALID;
+            self.enpassant_sq = SQUARE_INVALID;
         }
+        self.hash ^= self.state_key();
 
-        pos.flip();
-        pos
+        self.flip();
+        debug_assert_eq!(self.hash, self.compute_hash());
+        debug_assert!(self.mailbox_agrees());
+        undo
     }
 
-    pub fn piece_type_on(&self, sq: &Square) -> PieceType {
-        let sq_bb = Bitboard::from(sq);
+    // Reverses do_move, the captured piece comes from the move's capture bits.
+    // Pieces are put back without touching the hash as it is restored as a whole.
+    pub fn undo_move(&mut self, m: &Move, undo: &Undo) {
+        self.flip();
+        let from_sq = m.from_square();
+        let to_sq = m.to_square();
+
+        if m.is_move_type(MOVE_NORMAL | MOVE_DOUBLE_PUSH) {
+            let moving_pt = self.piece_type_on(&to_sq);
+            self.shift_piece(&to_sq, &from_sq, &moving_pt, &US);
+        } else if m.is_move_type(MOVE_CAPTURE) {
+            let moving_pt = self.piece_type_on(&to_sq);
+            self.shift_piece(&to_sq, &from_sq, &moving_pt, &US);
+            self.xor_piece(&to_sq, &m.capture_type(), &THEM);
+        } else if m.is_move_type(MOVE_ENPASSANT) {
+            self.shift_piece(&to_sq, &from_sq, &PAWN, &US);
+            self.xor_piece(&(to_sq - Square(8)), &PAWN, &THEM);
+        } else if m.is_move_type(MOVE_CASTLING) {
+            // Both pieces are lifted before either is put back as the squares may overlap
+            let (kto_sq, rto_sq) = castling_destinations(&from_sq, &to_sq);
+            self.xor_piece(&kto_sq, &KING, &US);
+            self.xor_piece(&rto_sq, &ROOK, &US);
+            self.xor_piece(&from_sq, &KING, &US);
+            self.xor_piece(&to_sq, &ROOK, &US);
+        } else if m.is_move_type(MOVE_PROM_CAP | MOVE_PROMOTION) {
+            self.xor_piece(&to_sq, &m.promotion_type(), &US);
+            self.xor_piece(&from_sq, &PAWN, &US);
+            if m.is_move_type(MOVE_PROM_CAP) {
+                self.xor_piece(&to_sq, &m.capture_type(), &THEM);
+            }
+        }
+
+        if self.side_to_move == BLACK {
+            self.fullmoves -= 1;
+        }
+        self.castling_rights = undo.castling_rights;
+        self.castling_rooks = undo.castling_rooks;
+        self.enpassant_sq = undo.enpassant_sq;
+        self.halfmoves = undo.halfmoves;
+        self.hash = undo.hash;
+        self.chess960 = undo.chess960;
+        debug_assert_eq!(self.hash, self.compute_hash());
+        debug_assert!(self.mailbox_agrees());
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // The key is defined on the real board, so squares and colors are mirrored
+    // back to white's point of view while black is to move
+    fn piece_key(&self, sq: &Square, pt: &PieceType, color: &Color) -> u64 {
+        if self.side_to_move == BLACK {
+            zobrist::piece_key(&Square(sq.0 ^ 56), pt, &!*color)
+        } else {
+            zobrist::piece_key(sq, pt, color)
+        }
+    }
+
+    // Castling rights and en-passant file contribution to the key
+    fn state_key(&self) -> u64 {
+        let mut real_cr = self.castling_rights;
+        if self.side_to_move == BLACK {
+            real_cr.0 = (real_cr.0 >> 2) | ((real_cr.0 & 3) << 2);
+        }
+        let mut key = zobrist::castling_key(&real_cr);
+        if self.enpassant_sq != SQUARE_INVALID {
+            key ^= zobrist::enpassant_key(&self.enpassant_sq.file());
+        }
+        key
+    }
+
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = self.state_key();
+        if self.side_to_move == BLACK {
+            hash ^= zobrist::side_key();
+        }
         for pt in PIECE_TYPES.iter() {
-            if self.piece_types[pt.0 as usize] & sq_bb != Bitboard(0) {
-                return *pt;
+            for color in COLORS.iter() {
+                for sq in self.pieces_of(pt, color) {
+                    hash ^= self.piece_key(&sq, pt, color);
+                }
             }
         }
-        PIECE_NONE
+        hash
     }
 
-    fn move_piece(&mut self, from: &Square, to: &Square, pt: &PieceType, color: &Color) {
+    pub fn pieces(&self, pt: &PieceType) -> Bitboard {
+        self.piece_types[pt.0 as usize]
+    }
+
+    pub fn pieces_of(&self, pt: &PieceType, color: &Color) -> Bitboard {
+        self.piece_types[pt.0 as usize] & self.colors[color.0 as usize]
+    }
+
+    pub fn occupancy(&self, color: &Color) -> Bitboard {
+        self.colors[color.0 as usize]
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.colors[US.0 as usize] | self.colors[THEM.0 as usize]
+    }
+
+    pub fn king_square(&self, color: &Color) -> Square {
+        Square(self.pieces_of(&KING, color).lsb() as u8)
+    }
+
+    pub fn enpassant_square(&self) -> Square {
+        self.enpassant_sq
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling_rights
+    }
+
+    pub fn castling_rook(&self, right: &CastlingRights) -> Square {
+        self.castling_rooks[right.0.trailing_zeros() as usize]
+    }
+
+    // A lost right forgets its rook so positions compare equal regardless of
+    // where the rook used to be. Chess960 only matters while castling is still
+    // possible, dropping it with the last right keeps to_fen unambiguous.
+    fn spoil_castling(&mut self, idx: usize) {
+        self.castling_rights.remove(CASTLING_RIGHTS[idx]);
+        self.castling_rooks[idx] = DEFAULT_CASTLING_ROOKS[idx];
+        if self.castling_rights == CASTLING_RIGHT_NONE {
+            self.chess960 = false;
+        }
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    // Has no effect without castling rights, as with those to_fen writes the same
+    // FEN in both modes. Setting it makes to_fen use Shredder-FEN file letters.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960 && self.castling_rights != CASTLING_RIGHT_NONE;
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    pub fn halfmoves(&self) -> u8 {
+        self.halfmoves
+    }
+
+    pub fn fullmoves(&self) -> u32 {
+        self.fullmoves
+    }
+
+    // Neither side can mate with any sequence of moves: bare kings, a single
+    // minor piece, or only bishops that all stand on squares of one color
+    pub fn is_insufficient_material(&self) -> bool {
+        if !(self.pieces(&PAWN) | self.pieces(&ROOK) | self.pieces(&QUEEN)).is_empty() {
+            return false;
+        }
+        let minors = self.pieces(&KNIGHT) | self.pieces(&BISHOP);
+        if minors.popcount() <= 1 {
+            return true;
+        }
+        let bishops = self.pieces(&BISHOP);
+        self.pieces(&KNIGHT).is_empty() && ((bishops & LIGHT_SQUARES_BB) == bishops || (bishops & DARK_SQUARES_BB) == bishops)
+    }
+
+    pub fn piece_type_on(&self, sq: &Square) -> PieceType {
+        self.board[self.board_index(sq)].0
+    }
+
+    // Piece on sq with its color relative to the side to move
+    pub fn piece_on(&self, sq: &Square) -> Option<ColoredPiece> {
+        let ColoredPiece(pt, real_color) = self.board[self.board_index(sq)];
+        if pt == PIECE_NONE {
+            None
+        } else {
+            Some(ColoredPiece(pt, Color(real_color.0 ^ self.side_to_move.0)))
+        }
+    }
+
+    fn board_index(&self, sq: &Square) -> usize {
+        if self.side_to_move == BLACK {
+            (sq.0 ^ 56) as usize
+        } else {
+            sq.0 as usize
+        }
+    }
+
+    // Whether the mailbox holds exactly the pieces of the bitboards
+    fn mailbox_agrees(&self) -> bool {
+        SQUARES.iter().all(|sq| {
+            let bb_piece = PIECE_TYPES
+                .iter()
+                .find(|pt| self.piece_types[pt.0 as usize].contains(sq))
+                .map(|pt| ColoredPiece(*pt, if self.colors[US.0 as usize].contains(sq) { US } else { THEM }));
+            self.piece_on(sq) == bb_piece
+        })
+    }
+
+    // Pieces of both colors attacking sq given the occupancy, which may differ
+    // from the board's own to look through pieces that are about to move
+    pub fn attackers_to(&self, sq: &Square, occupancy: &Bitboard) -> Bitboard {
+        let bishops_queens = self.pieces(&BISHOP) | self.pieces(&QUEEN);
+        let rooks_queens = self.pieces(&ROOK) | self.pieces(&QUEEN);
+        (pawn_attacks(sq, &THEM) & self.pieces_of(&PAWN, &US))
+            | (pawn_attacks(sq, &US) & self.pieces_of(&PAWN, &THEM))
+            | (attacks(&KNIGHT, sq, occupancy) & self.pieces(&KNIGHT))
+            | (attacks(&KING, sq, occupancy) & self.pieces(&KING))
+            | (attacks(&BISHOP, sq, occupancy) & bishops_queens)
+            | (attacks(&ROOK, sq, occupancy) & rooks_queens)
+    }
+
+    pub fn is_square_attacked(&self, sq: &Square, by: &Color) -> bool {
+        !(self.attackers_to(sq, &self.occupied()) & self.occupancy(by)).is_empty()
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.is_square_attacked(&self.king_square(&US), &THEM)
+    }
+
+    // Enemy pieces giving check to our king
+    pub fn checkers(&self) -> Bitboard {
+        self.attackers_to(&self.king_square(&US), &self.occupied()) & self.occupancy(&THEM)
+    }
+
+    // Our pieces that are the only blocker between our king and an enemy slider
+    pub fn pinned_pieces(&self) -> Bitboard {
+        let king_sq = self.king_square(&US);
+        let bishops_queens = self.pieces_of(&BISHOP, &THEM) | self.pieces_of(&QUEEN, &THEM);
+        let rooks_queens = self.pieces_of(&ROOK, &THEM) | self.pieces_of(&QUEEN, &THEM);
+        let snipers = (attacks(&BISHOP, &king_sq, &Bitboard(0)) & bishops_queens)
+            | (attacks(&ROOK, &king_sq, &Bitboard(0)) & rooks_queens);
+
+        let mut pinned = Bitboard(0);
+        for sniper_sq in snipers {
+            let blockers = between(&king_sq, &sniper_sq) & self.occupied();
+            if blockers.popcount() == 1 {
+                pinned |= blockers & self.occupancy(&US);
+            }
+        }
+        pinned
+    }
+
+    // Adds the piece to an empty square or removes it from its square, the hash
+    // is left alone
+    fn xor_piece(&mut self, sq: &Square, pt: &PieceType, color: &Color) {
+        let sq_bb = Bitboard::from(sq);
+        self.colors[color.0 as usize] ^= sq_bb;
+        self.piece_types[pt.0 as usize] ^= sq_bb;
+        let idx = self.board_index(sq);
+        self.board[idx] = if self.board[idx].0 == PIECE_NONE {
+            ColoredPiece(*pt, Color(color.0 ^ self.side_to_move.0))
+        } else {
+            NO_PIECE
+        };
+    }
+
+    fn shift_piece(&mut self, from: &Square, to: &Square, pt: &PieceType, color: &Color) {
         let from_to_mask = Bitboard::from(from) ^ Bitboard::from(to);
         self.colors[color.0 as usize] ^= from_to_mask;
         self.piece_types[pt.0 as usize] ^= from_to_mask;
+        let (from_idx, to_idx) = (self.board_index(from), self.board_index(to));
+        self.board[to_idx] = self.board[from_idx];
+        self.board[from_idx] = NO_PIECE;
+    }
+
+    fn move_piece(&mut self, from: &Square, to: &Square, pt: &PieceType, color: &Color) {
+        self.shift_piece(from, to, pt, color);
+        self.hash ^= self.piece_key(from, pt, color) ^ self.piece_key(to, pt, color);
     }
 
     fn toggle_piece(&mut self, sq: &Square, pt: &PieceType, color: &Color) {
-        let toggle_mask = Bitboard::from(sq);
-        self.colors[color.0 as usize] ^= toggle_mask;
-        self.piece_types[pt.0 as usize] ^= toggle_mask;
+        self.xor_piece(sq, pt, color);
+        self.hash ^= self.piece_key(sq, pt, color);
     }
 
     pub fn print_parts(&self) {
@@ -198,5 +665,295 @@ impl Position {
         self.enpassant_sq.print();
         println!("{}", self.halfmoves);
         println!("{}", self.fullmoves);
+        println!("{:016x}", self.hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Violation;
+
+    // Every position reachable within depth must parse back from its own FEN
+    fn assert_round_trip(pos: &Position, depth: u32) {
+        let fen = pos.to_fen();
+        assert!(Position::from(fen.as_str()) == *pos, "FEN round trip failed for {}", fen);
+        if depth > 0 {
+            for m in pos.legal_moves().iter() {
+                assert_round_trip(&pos.make_move(m), depth - 1);
+            }
+        }
+    }
+
+    fn assert_suite_round_trip(fen: &str) {
+        let pos = Position::from(fen);
+        assert_eq!(pos.to_fen(), fen);
+        assert_round_trip(&pos, 2);
+    }
+
+    #[test]
+    fn initial_position() {
+        assert_suite_round_trip(INITIAL_FEN);
+    }
+
+    #[test]
+    fn cpw_positions() {
+        assert_suite_round_trip("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_suite_round_trip("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_suite_round_trip("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1");
+        assert_suite_round_trip("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8");
+        assert_suite_round_trip("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10");
+    }
+
+    #[test]
+    fn black_to_move() {
+        assert_suite_round_trip("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1");
+        assert_suite_round_trip("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_suite_round_trip("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+    }
+
+    #[test]
+    fn chess960_positions() {
+        assert_suite_round_trip("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        assert_suite_round_trip("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9");
+        assert_suite_round_trip("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9");
+    }
+
+    #[test]
+    fn xfen_outermost_rook() {
+        // K names the outermost rook even with another one between it and the king
+        let pos = Position::from("4k3/8/8/8/8/8/8/R3KR1R w KQ - 0 1");
+        assert!(pos.castling_rook(&WHITE_KING_SIDE) == H1);
+        assert!(pos.castling_rook(&WHITE_QUEEN_SIDE) == A1);
+        assert!(!pos.is_chess960());
+        assert_eq!(pos.to_fen(), "4k3/8/8/8/8/8/8/R3KR1R w KQ - 0 1");
+    }
+
+    #[test]
+    fn xfen_inner_rook() {
+        // An inner rook is named by its file, which makes the position Chess960
+        let pos = Position::from("4k3/8/8/8/8/8/8/R3KR1R w FQ - 0 1");
+        assert!(pos.castling_rook(&WHITE_KING_SIDE) == F1);
+        assert!(pos.is_chess960());
+        assert_eq!(pos.to_fen(), "4k3/8/8/8/8/8/8/R3KR1R w FA - 0 1");
+
+        let pos = Position::from("rr2k3/8/8/8/8/8/8/4K3 b q - 0 1");
+        assert!(!pos.is_chess960());
+        assert_eq!(pos.to_fen(), "rr2k3/8/8/8/8/8/8/4K3 b q - 0 1");
+        let pos = Position::from("rr2k3/8/8/8/8/8/8/4K3 b b - 0 1");
+        assert!(pos.is_chess960());
+        assert_eq!(pos.to_fen(), "rr2k3/8/8/8/8/8/8/4K3 b b - 0 1");
+    }
+
+    #[test]
+    fn shredder_castling() {
+        let pos = Position::from("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1");
+        assert!(pos.is_chess960());
+        assert!(pos.castling_rook(&WHITE_KING_SIDE) == G1);
+        assert!(pos.castling_rook(&WHITE_QUEEN_SIDE) == B1);
+        assert!(pos.castling_rook(&BLACK_KING_SIDE) == G8);
+        assert!(pos.castling_rook(&BLACK_QUEEN_SIDE) == B8);
+
+        // Letters of the corner files still mean Chess960
+        let pos = Position::from("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1");
+        assert!(pos.is_chess960());
+        assert_eq!(pos.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1");
+    }
+
+    #[test]
+    fn kqkq_does_not_imply_chess960() {
+        let mut pos = Position::from("r3k2r/8/8/8/8/8/8/R4K1R w KQkq - 0 1");
+        assert!(!pos.is_chess960());
+        assert_eq!(
+            pos.validate(),
+            vec![Violation::CastlingWithoutKing(WHITE_KING_SIDE), Violation::CastlingWithoutKing(WHITE_QUEEN_SIDE)]
+        );
+        // As with UCI_Chess960 set, the rooks outside the king are the castling rooks
+        pos.set_chess960(true);
+        assert!(pos.validate().is_empty());
+        assert_eq!(pos.to_fen(), "r3k2r/8/8/8/8/8/8/R4K1R w HAha - 0 1");
+    }
+
+    #[test]
+    fn chess960_needs_castling_rights() {
+        let mut pos = Position::from("4k3/8/8/8/8/8/8/R3K2R w - - 0 1");
+        pos.set_chess960(true);
+        assert!(!pos.is_chess960());
+
+        // Losing the last right leaves a position that reads back from its FEN
+        let pos = Position::from("4k3/8/8/8/8/8/8/4K1R1 w G - 0 1");
+        let child = pos.make_move(&pos.parse_uci_move("e1f1").unwrap());
+        assert!(!child.is_chess960());
+        assert!(Position::from(child.to_fen().as_str()) == child);
+    }
+
+    #[test]
+    fn undo_move_restores_position() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            // Moving the king drops the last right and with it Chess960
+            "4k3/8/8/8/8/8/8/4K1R1 w G - 0 1",
+        ];
+        for fen in fens.iter() {
+            let original = Position::from(*fen);
+            let mut pos = original;
+            for m in original.legal_moves().iter() {
+                let undo = pos.do_move(m);
+                assert!(pos == original.make_move(m));
+                pos.undo_move(m, &undo);
+                assert!(pos == original, "undo of {} in {}", original.move_to_uci(m), fen);
+            }
+        }
+    }
+
+    #[test]
+    fn display_matches_to_fen() {
+        let pos = Position::from("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1");
+        assert_eq!(pos.to_string(), pos.to_fen());
+    }
+
+    fn fen_error(fen: &str) -> Option<FenError> {
+        Position::from_fen(fen).err()
+    }
+
+    #[test]
+    fn invalid_piece() {
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1"),
+            Some(FenError::InvalidPiece('X'))
+        );
+    }
+
+    #[test]
+    fn invalid_rank_length() {
+        assert_eq!(
+            fen_error("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Some(FenError::InvalidRankLength(RANK_7))
+        );
+        assert_eq!(
+            fen_error("rnbqkbnrr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Some(FenError::InvalidRankLength(RANK_8))
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/333/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Some(FenError::InvalidRankLength(RANK_3))
+        );
+    }
+
+    #[test]
+    fn too_many_ranks() {
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Some(FenError::TooManyRanks)
+        );
+    }
+
+    #[test]
+    fn too_few_ranks() {
+        assert_eq!(fen_error("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), Some(FenError::TooFewRanks));
+    }
+
+    #[test]
+    fn invalid_side_to_move() {
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+            Some(FenError::InvalidSideToMove(String::from("x")))
+        );
+    }
+
+    #[test]
+    fn invalid_castling_right() {
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkz - 0 1"),
+            Some(FenError::InvalidCastlingRight('z'))
+        );
+    }
+
+    #[test]
+    fn invalid_enpassant_square() {
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e4 0 1"),
+            Some(FenError::InvalidEnpassantSquare(String::from("e4")))
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq j3 0 1"),
+            Some(FenError::InvalidEnpassantSquare(String::from("j3")))
+        );
+    }
+
+    #[test]
+    fn invalid_clocks() {
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1"),
+            Some(FenError::InvalidHalfmoves(String::from("x")))
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1"),
+            Some(FenError::InvalidFullmoves(String::from("-1")))
+        );
+    }
+
+    #[test]
+    fn missing_field() {
+        assert_eq!(fen_error(""), Some(FenError::MissingField(FenStage::Pieces)));
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"),
+            Some(FenError::MissingField(FenStage::SideToMove))
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq"),
+            Some(FenError::MissingField(FenStage::EnpassantSquare))
+        );
+    }
+
+    #[test]
+    fn optional_clocks() {
+        let pos = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").unwrap();
+        assert_eq!(pos.to_fen(), INITIAL_FEN);
+    }
+
+    fn sq(name: &str) -> Square {
+        Square::try_from(name).unwrap()
+    }
+
+    fn bb(squares: &[&str]) -> Bitboard {
+        squares.iter().fold(Bitboard(0), |bb, name| bb | Bitboard::from(&sq(name)))
+    }
+
+    #[test]
+    fn attackers_to_square() {
+        let pos = Position::from("4k3/8/8/3p4/4P3/2N5/3Q4/3R2K1 w - - 0 1");
+        let d5 = sq("d5");
+        assert_eq!(pos.attackers_to(&d5, &pos.occupied()), bb(&["e4", "c3", "d2"]));
+        // Without the queen the rook behind it joins in
+        let occupancy = pos.occupied() & !bb(&["d2"]);
+        assert_eq!(pos.attackers_to(&d5, &occupancy), bb(&["e4", "c3", "d2", "d1"]));
+        assert!(pos.is_square_attacked(&d5, &US));
+        assert!(pos.is_square_attacked(&sq("e4"), &THEM));
+        assert!(!pos.is_square_attacked(&sq("d4"), &THEM));
+    }
+
+    #[test]
+    fn checks() {
+        let pos = Position::from("4k3/8/8/8/8/3n4/8/r3K3 w - - 0 1");
+        assert!(pos.in_check());
+        assert_eq!(pos.checkers(), bb(&["d3", "a1"]));
+        // Squares are seen from black, the bishop on b5 shows up on b4
+        let pos = Position::from("4k3/8/8/1B6/8/8/8/4K3 b - - 0 1");
+        assert!(pos.in_check());
+        assert_eq!(pos.checkers(), bb(&["b4"]));
+        assert!(!Position::from(INITIAL_FEN).in_check());
+        assert!(Position::from(INITIAL_FEN).checkers().is_empty());
+    }
+
+    #[test]
+    fn pinned_pieces() {
+        let pos = Position::from("k3r3/8/8/b7/8/2N5/4P3/4KB1r w - - 0 1");
+        assert_eq!(pos.pinned_pieces(), bb(&["c3", "e2", "f1"]));
+        // A second blocker on the file breaks the pin
+        let pos = Position::from("k3r3/8/8/b7/4P3/2N5/4P3/4KB1r w - - 0 1");
+        assert_eq!(pos.pinned_pieces(), bb(&["c3", "f1"]));
     }
 }
@@ -0,0 +1,590 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::constants::*;
+use crate::eval::evaluate;
+use crate::game::Game;
+use crate::movegen::MoveList;
+use crate::movepick::{History, MoveContext, MovePicker};
+use crate::position::Position;
+use crate::timeman::TimeManager;
+use crate::tt::{score_from_tt, score_to_tt, Bound, TTEntry, TranspositionTable};
+use crate::types::Move;
+
+pub const MAX_PLY: usize = 128;
+pub const INFINITY: i32 = 32000;
+pub const MATE: i32 = 31000;
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+pub const MAX_THREADS: usize = 256;
+
+// Helper threads skip iterations in staggered patterns so they spread over
+// different depths instead of repeating the main thread's work
+const SKIP_SIZE: [u32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+    // Milliseconds lost to communication per move, taken off the clock
+    pub move_overhead: u64,
+}
+
+// Last completed iteration of one thread
+#[derive(Copy, Clone)]
+struct ThreadResult {
+    best_move: Option<Move>,
+    score: i32,
+    depth: u32,
+}
+
+struct Search {
+    limits: SearchLimits,
+    // Thread 0 is the main thread, the only one that reports and watches the clock
+    id: usize,
+    stop: Arc<AtomicBool>,
+    tt: Arc<TranspositionTable>,
+    // Node counts of all threads, each thread publishes its own
+    node_counts: Arc<Vec<AtomicU64>>,
+    time: TimeManager,
+    // Deepest iteration finished, the clock cannot stop the search before the
+    // first one so there is always a searched move to play
+    completed_depth: u32,
+    stopped: bool,
+    nodes: u64,
+    pv: [[Move; MAX_PLY]; MAX_PLY],
+    pv_len: [usize; MAX_PLY],
+    history: History,
+    // Move played at each ply of the current line, for the counter-move and
+    // continuation tables
+    played: [Option<MoveContext>; MAX_PLY],
+    // Zobrist keys of the game followed by the keys of the current line
+    keys: Vec<u64>,
+}
+
+impl Search {
+    fn new(
+        game: &Game,
+        limits: SearchLimits,
+        id: usize,
+        stop: Arc<AtomicBool>,
+        tt: Arc<TranspositionTable>,
+        node_counts: Arc<Vec<AtomicU64>>,
+    ) -> Search {
+        Search {
+            limits,
+            id,
+            stop,
+            tt,
+            node_counts,
+            time: TimeManager::new(game.position(), &limits),
+            completed_depth: 0,
+            stopped: false,
+            nodes: 0,
+            pv: [[Move(0); MAX_PLY]; MAX_PLY],
+            pv_len: [0; MAX_PLY],
+            history: History::new(),
+            played: [None; MAX_PLY],
+            keys: game.keys().to_vec(),
+        }
+    }
+
+    fn check_stop(&mut self) {
+        self.node_counts[self.id].store(self.nodes, Ordering::Relaxed);
+        let out_of_time = self.id == 0 && self.completed_depth > 0 && self.time.hard_limit_reached();
+        if self.stop.load(Ordering::Relaxed) || out_of_time {
+            self.stopped = true;
+        }
+    }
+
+    // Any earlier occurrence counts, repeating once is as good as a threefold
+    // repetition for the search. Only positions since the last irreversible move
+    // with the same side to move are candidates.
+    fn is_repetition(&self, pos: &Position) -> bool {
+        let current = self.keys.len() - 1;
+        let window = (pos.halfmoves() as usize).min(current);
+        (4..=window).step_by(2).any(|distance| self.keys[current - distance] == self.keys[current])
+    }
+
+    // A checkmate delivered on the hundredth half-move still wins
+    fn is_draw(&self, pos: &Position) -> bool {
+        self.is_repetition(pos)
+            || pos.is_insufficient_material()
+            || (pos.halfmoves() >= 100 && !(pos.in_check() && pos.legal_moves().is_empty()))
+    }
+
+    fn total_nodes(&self) -> u64 {
+        self.node_counts.iter().map(|nodes| nodes.load(Ordering::Relaxed)).sum()
+    }
+
+    // Moves that led to ply, the latest first
+    fn context(&self, ply: usize) -> [Option<MoveContext>; 2] {
+        let prev = |back: usize| if ply >= back { self.played[ply - back] } else { None };
+        [prev(1), prev(2)]
+    }
+
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        self.pv[ply][ply] = m;
+        for next_ply in ply + 1..self.pv_len[ply + 1] {
+            self.pv[ply][next_ply] = self.pv[ply + 1][next_ply];
+        }
+        self.pv_len[ply] = self.pv_len[ply + 1].max(ply + 1);
+    }
+
+    fn quiescence(&mut self, pos: &Position, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        if self.nodes & 2047 == 0 {
+            self.check_stop();
+        }
+        if self.stopped {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let stand_pat = evaluate(pos);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut captures = MoveList::new();
+        for m in pos.pseudo_legal_moves().iter() {
+            if m.is_move_type(MOVE_MASK_CAPTURE) {
+                captures.push(*m);
+            }
+        }
+
+        let mut picker = MovePicker::captures(&captures);
+        while let Some(m) = picker.next(pos, &self.history) {
+            // Captures that lose material cannot raise alpha above the stand pat
+            if !pos.is_legal(&m) || !pos.see_ge(&m, 0) {
+                continue;
+            }
+            let score = -self.quiescence(&pos.make_move(&m), -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+
+    fn negamax(&mut self, pos: &Position, mut alpha: i32, mut beta: i32, depth: i32, ply: usize) -> i32 {
+        self.pv_len[ply] = ply;
+        if depth <= 0 {
+            return self.quiescence(pos, alpha, beta, ply);
+        }
+        if self.nodes & 2047 == 0 {
+            self.check_stop();
+        }
+        if self.stopped {
+            return 0;
+        }
+        self.nodes += 1;
+
+        if ply > 0 {
+            if self.is_draw(pos) {
+                return 0;
+            }
+            if ply >= MAX_PLY - 1 {
+                return evaluate(pos);
+            }
+            // Mate distance pruning, no line from here can beat a shorter mate already found
+            alpha = alpha.max(-MATE + ply as i32);
+            beta = beta.min(MATE - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        // Cutoffs from the table are only taken outside the principal variation so
+        // that the reported PV stays complete
+        let tt_entry = self.tt.probe(pos.hash());
+        let tt_move = tt_entry.map_or(0, |entry| entry.best_move);
+        if let Some(entry) = tt_entry {
+            let is_pv = beta - alpha > 1;
+            if !is_pv && entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let moves = pos.legal_moves();
+        if moves.is_empty() {
+            return if pos.in_check() { -MATE + ply as i32 } else { 0 };
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = Move(0);
+        let context = self.context(ply);
+        let mut picker = MovePicker::new(
+            &moves,
+            tt_move,
+            self.history.killers(ply),
+            self.history.counter_move(&context[0]),
+            context,
+        );
+        let mut quiets_tried = MoveList::new();
+        let mut searched = 0;
+        while let Some(m) = picker.next(pos, &self.history) {
+            let is_quiet = !m.is_move_type(MOVE_MASK_CAPTURE | MOVE_PROMOTION);
+            if is_quiet {
+                quiets_tried.push(m);
+            }
+            self.played[ply] = Some((pos.piece_type_on(&m.from_square()), m.to_square()));
+            let child = pos.make_move(&m);
+            self.keys.push(child.hash());
+            let mut score;
+            searched += 1;
+            if searched == 1 {
+                score = -self.negamax(&child, -beta, -alpha, depth - 1, ply + 1);
+            } else {
+                score = -self.negamax(&child, -alpha - 1, -alpha, depth - 1, ply + 1);
+                if score > alpha && score < beta {
+                    score = -self.negamax(&child, -beta, -alpha, depth - 1, ply + 1);
+                }
+            }
+            self.keys.pop();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = m;
+                    self.update_pv(ply, m);
+                    if alpha >= beta {
+                        if is_quiet {
+                            self.history.update_quiet(pos, &m, ply, depth, &context, &quiets_tried);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(
+            pos.hash(),
+            &TTEntry {
+                best_move: best_move.compressed(),
+                score: score_to_tt(best_score, ply),
+                depth,
+                bound,
+            },
+        );
+        best_score
+    }
+
+    fn print_info(&self, pos: &Position, depth: u32, score: i32) {
+        let elapsed = self.time.elapsed();
+        let nodes = self.total_nodes();
+        let nps = (nodes as u128 * 1000) / elapsed.as_millis().max(1);
+        let score_str = if score >= MATE_BOUND {
+            format!("mate {}", (MATE - score + 1) / 2)
+        } else if score <= -MATE_BOUND {
+            format!("mate {}", -(MATE + score) / 2)
+        } else {
+            format!("cp {}", score)
+        };
+
+        let mut pv_str = String::new();
+        let mut pv_pos = *pos;
+        for m in self.pv[0][..self.pv_len[0]].iter() {
+            pv_str.push(' ');
+            pv_str.push_str(&pv_pos.move_to_uci(m));
+            pv_pos = pv_pos.make_move(m);
+        }
+
+        println!(
+            "info depth {} score {} nodes {} nps {} hashfull {} time {} pv{}",
+            depth,
+            score_str,
+            nodes,
+            nps,
+            self.tt.hashfull(),
+            elapsed.as_millis(),
+            pv_str
+        );
+    }
+
+    fn iterative_deepening(&mut self, pos: &Position) -> ThreadResult {
+        let root_moves = pos.legal_moves();
+        let mut result = ThreadResult {
+            best_move: root_moves.iter().next().copied(),
+            score: -INFINITY,
+            depth: 0,
+        };
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as u32 - 1).min(MAX_PLY as u32 - 1);
+
+        for depth in 1..=max_depth {
+            if skips_iteration(self.id, depth) {
+                continue;
+            }
+            let score = self.negamax(pos, -INFINITY, INFINITY, depth as i32, 0);
+            self.node_counts[self.id].store(self.nodes, Ordering::Relaxed);
+            if self.stopped {
+                break;
+            }
+            if self.pv_len[0] > 0 {
+                result.best_move = Some(self.pv[0][0]);
+            }
+            result.score = score;
+            result.depth = depth;
+            self.completed_depth = depth;
+            if self.id > 0 {
+                continue;
+            }
+            self.print_info(pos, depth, score);
+
+            // Only one legal move or a forced mate found, deeper iterations cannot change the result
+            if !self.limits.infinite && (root_moves.len() == 1 || score.abs() >= MATE_BOUND) && self.limits.depth.is_none() {
+                break;
+            }
+            if let Some(best_move) = result.best_move {
+                if self.time.iteration_done(&best_move, score) {
+                    break;
+                }
+            }
+        }
+        result
+    }
+}
+
+// The main thread searches every depth, helper i skips the odd blocks of
+// SKIP_SIZE depths shifted by SKIP_PHASE
+fn skips_iteration(id: usize, depth: u32) -> bool {
+    if id == 0 {
+        return false;
+    }
+    let idx = (id - 1) % SKIP_SIZE.len();
+    ((depth + SKIP_PHASE[idx]) / SKIP_SIZE[idx]) & 1 == 1
+}
+
+// Every thread votes for its move with its score and depth, a proven mate
+// overrides the vote
+fn vote(results: &[ThreadResult]) -> Option<Move> {
+    let completed: Vec<&ThreadResult> = results.iter().filter(|result| result.depth > 0).collect();
+    let min_score = match completed.iter().map(|result| result.score).min() {
+        Some(min_score) => min_score,
+        None => return results.first().and_then(|result| result.best_move),
+    };
+    let votes = |m: Option<Move>| -> i64 {
+        completed
+            .iter()
+            .filter(|result| result.best_move == m)
+            .map(|result| (result.score - min_score + 14) as i64 * result.depth as i64)
+            .sum()
+    };
+
+    let mut best = completed[0];
+    for result in completed.iter().skip(1) {
+        if best.score >= MATE_BOUND || result.score >= MATE_BOUND {
+            if result.score > best.score {
+                best = result;
+            }
+        } else if votes(result.best_move) > votes(best.best_move) {
+            best = result;
+        }
+    }
+    best.best_move
+}
+
+// Lazy SMP, all threads search the same root and share only the table. The
+// main thread decides when to stop, then raises the stop flag for the helpers.
+// Runs until the stop flag is raised in infinite mode as the protocol requires.
+// The moves of the game are needed to recognise repetitions.
+pub fn search(
+    game: &Game,
+    limits: SearchLimits,
+    threads: usize,
+    stop: Arc<AtomicBool>,
+    tt: Arc<TranspositionTable>,
+) -> Option<Move> {
+    tt.new_search();
+    let pos = game.position();
+    let threads = threads.clamp(1, MAX_THREADS);
+    let node_counts: Arc<Vec<AtomicU64>> = Arc::new((0..threads).map(|_| AtomicU64::new(0)).collect());
+
+    let helpers: Vec<thread::JoinHandle<ThreadResult>> = (1..threads)
+        .map(|id| {
+            let game = game.clone();
+            let stop = Arc::clone(&stop);
+            let tt = Arc::clone(&tt);
+            let node_counts = Arc::clone(&node_counts);
+            thread::spawn(move || {
+                Search::new(&game, limits, id, stop, tt, node_counts).iterative_deepening(game.position())
+            })
+        })
+        .collect();
+
+    let mut search = Search::new(game, limits, 0, Arc::clone(&stop), tt, node_counts);
+    let mut results = vec![search.iterative_deepening(pos)];
+
+    while limits.infinite && !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(1));
+    }
+    stop.store(true, Ordering::Relaxed);
+    results.extend(helpers.into_iter().map(|helper| helper.join().unwrap()));
+
+    let best_move = vote(&results);
+
+    match best_move {
+        Some(m) => println!("bestmove {}", pos.move_to_uci(&m)),
+        None => println!("bestmove 0000"),
+    }
+    best_move
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+
+    fn best_move(fen: &str, depth: u32) -> Option<String> {
+        let pos = Position::from(fen);
+        let stop = Arc::new(AtomicBool::new(false));
+        let best_move = search(&Game::new(pos), limits(depth), 1, stop, Arc::new(TranspositionTable::new(1)));
+        best_move.map(|m| pos.move_to_uci(&m))
+    }
+
+    fn root_score(fen: &str, depth: i32) -> i32 {
+        let pos = Position::from(fen);
+        let stop = Arc::new(AtomicBool::new(false));
+        let node_counts = Arc::new(vec![AtomicU64::new(0)]);
+        let tt = Arc::new(TranspositionTable::new(1));
+        let mut search = Search::new(&Game::new(pos), limits(depth as u32), 0, stop, tt, node_counts);
+        search.negamax(&pos, -INFINITY, INFINITY, depth, 0)
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        assert_eq!(best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3), Some(String::from("a1a8")));
+        assert_eq!(root_score("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3), MATE - 1);
+    }
+
+    #[test]
+    fn finds_mate_for_black() {
+        assert_eq!(best_move("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", 3), Some(String::from("a8a1")));
+    }
+
+    #[test]
+    fn wins_hanging_queen() {
+        assert_eq!(best_move("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", 2), Some(String::from("d1d5")));
+    }
+
+    #[test]
+    fn checkmated_and_stalemated() {
+        assert_eq!(best_move("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 1), None);
+        assert_eq!(root_score("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 1), -MATE);
+        assert_eq!(root_score("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 1), 0);
+    }
+    #[test]
+    fn draws_score_zero() {
+        // Every move reaches the hundredth half-move without mating
+        assert_eq!(root_score("7k/8/8/8/8/8/8/R5K1 w - - 99 80", 3), 0);
+        // Only a mate on the last half-move still wins
+        assert_eq!(root_score("7k/8/6K1/8/8/8/8/R7 w - - 99 80", 3), MATE - 1);
+        // Taking the queen leaves bare kings
+        assert_eq!(root_score("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1", 2), 0);
+    }
+    #[test]
+    fn helper_threads_find_the_same_mate() {
+        let pos = Position::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let stop = Arc::new(AtomicBool::new(false));
+        let best_move = search(&Game::new(pos), limits(4), 4, stop, Arc::new(TranspositionTable::new(1)));
+        assert_eq!(best_move.map(|m| pos.move_to_uci(&m)), Some(String::from("a1a8")));
+    }
+
+    #[test]
+    fn skip_tables() {
+        assert_eq!(SKIP_SIZE.len(), SKIP_PHASE.len());
+        assert!((1..20).all(|depth| !skips_iteration(0, depth)));
+        // The first two helpers take alternate depths
+        assert!((1..20).all(|depth| skips_iteration(1, depth) != skips_iteration(2, depth)));
+        // Every helper searches half of each window of 2 * SKIP_SIZE depths
+        for id in 1..=SKIP_SIZE.len() {
+            let window = 2 * SKIP_SIZE[id - 1];
+            let searched = (1..=window).filter(|depth| !skips_iteration(id, *depth)).count() as u32;
+            assert_eq!(searched, window / 2, "helper {}", id);
+        }
+        // Helpers beyond the table reuse it from the start
+        assert!((1..20).all(|depth| skips_iteration(1, depth) == skips_iteration(1 + SKIP_SIZE.len(), depth)));
+    }
+
+    fn result(m: u32, score: i32, depth: u32) -> ThreadResult {
+        ThreadResult {
+            best_move: Some(Move(m)),
+            score,
+            depth,
+        }
+    }
+
+    #[test]
+    fn vote_prefers_agreement_of_deep_searches() {
+        // Two threads agreeing at depth 8 outweigh one at depth 9 with a slightly better score
+        let results = [result(1, 20, 8), result(2, 24, 9), result(1, 20, 8)];
+        assert_eq!(vote(&results), Some(Move(1)));
+        // A single thread that saw much further wins on its own
+        let results = [result(1, 20, 4), result(2, 20, 12), result(1, 20, 4)];
+        assert_eq!(vote(&results), Some(Move(2)));
+    }
+
+    #[test]
+    fn vote_prefers_the_shortest_mate() {
+        let results = [result(1, 50, 12), result(2, MATE - 5, 6), result(3, MATE - 3, 5), result(1, 50, 12)];
+        assert_eq!(vote(&results), Some(Move(3)));
+    }
+
+    #[test]
+    fn vote_without_completed_iterations() {
+        let results = [result(1, -INFINITY, 0), result(2, -INFINITY, 0)];
+        assert_eq!(vote(&results), Some(Move(1)));
+    }
+    #[test]
+    fn low_clock_completes_the_first_iteration() {
+        let pos = Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let limits = SearchLimits {
+            wtime: Some(0),
+            ..SearchLimits::default()
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let node_counts = Arc::new(vec![AtomicU64::new(0)]);
+        let tt = Arc::new(TranspositionTable::new(1));
+        let result = Search::new(&Game::new(pos), limits, 0, stop, tt, node_counts).iterative_deepening(&pos);
+        assert!(result.depth >= 1);
+        assert!(result.best_move.is_some());
+    }
+}
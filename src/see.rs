@@ -0,0 +1,235 @@
+use crate::attacks::{bishop_attacks, rook_attacks};
+use crate::constants::*;
+use crate::position::Position;
+use crate::types::{Bitboard, Color, Move, PieceType, Square};
+
+impl Position {
+    // Material won by the piece arriving on the target square, including the
+    // promotion gain
+    fn see_gain(&self, m: &Move) -> i32 {
+        let mut gain = 0;
+        if m.is_move_type(MOVE_MASK_CAPTURE) {
+            gain += SEE_PIECE_VALUES[m.capture_type().0 as usize];
+        }
+        if m.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP) {
+            gain += SEE_PIECE_VALUES[m.promotion_type().0 as usize] - SEE_PIECE_VALUES[PAWN.0 as usize];
+        }
+        gain
+    }
+
+    // Value of the piece standing on the target square once m is played
+    fn see_moved_value(&self, m: &Move) -> i32 {
+        if m.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP) {
+            SEE_PIECE_VALUES[m.promotion_type().0 as usize]
+        } else {
+            SEE_PIECE_VALUES[self.piece_type_on(&m.from_square()).0 as usize]
+        }
+    }
+
+    // Occupancy after m with the moving piece and any en passant victim gone,
+    // the target square stays occupied either way
+    fn see_occupancy(&self, m: &Move) -> Bitboard {
+        let to = m.to_square();
+        let mut occupancy = self.occupied() ^ Bitboard::from(&m.from_square()) | Bitboard::from(&to);
+        if m.is_move_type(MOVE_ENPASSANT) {
+            occupancy ^= Bitboard::from(&Square(to.0 - 8));
+        }
+        occupancy
+    }
+
+    fn least_valuable_attacker(&self, attackers: &Bitboard, color: &Color) -> Option<(PieceType, Square)> {
+        let attackers = *attackers & self.occupancy(color);
+        PIECE_TYPES.iter().find_map(|pt| {
+            let bb = attackers & self.pieces(pt);
+            if bb.is_empty() {
+                None
+            } else {
+                Some((*pt, Square(bb.lsb() as u8)))
+            }
+        })
+    }
+
+    // Sliders behind a piece that just left join the exchange, the king may
+    // stand on either kind of line
+    fn see_xrays(&self, to: &Square, pt: &PieceType, occupancy: &Bitboard) -> Bitboard {
+        let mut xrays = Bitboard(0);
+        if *pt == PAWN || *pt == BISHOP || *pt == QUEEN || *pt == KING {
+            xrays |= bishop_attacks(to, occupancy) & (self.pieces(&BISHOP) | self.pieces(&QUEEN));
+        }
+        if *pt == ROOK || *pt == QUEEN || *pt == KING {
+            xrays |= rook_attacks(to, occupancy) & (self.pieces(&ROOK) | self.pieces(&QUEEN));
+        }
+        xrays
+    }
+
+    // Net material the mover wins from the exchange m starts on its target
+    // square, both sides recapturing with their least valuable piece and free
+    // to stop. Pins are ignored and the king only captures last.
+    pub fn see(&self, m: &Move) -> i32 {
+        if m.is_move_type(MOVE_CASTLING) {
+            return 0;
+        }
+        let to = m.to_square();
+        let mut occupancy = self.see_occupancy(m);
+        let mut attackers = self.attackers_to(&to, &occupancy) & occupancy;
+        let mut gains = [0; 32];
+        gains[0] = self.see_gain(m);
+        let mut victim_value = self.see_moved_value(m);
+        let mut color = THEM;
+        let mut depth = 0;
+
+        while let Some((pt, sq)) = self.least_valuable_attacker(&attackers, &color) {
+            depth += 1;
+            gains[depth] = victim_value - gains[depth - 1];
+            victim_value = SEE_PIECE_VALUES[pt.0 as usize];
+            occupancy ^= Bitboard::from(&sq);
+            attackers |= self.see_xrays(&to, &pt, &occupancy);
+            attackers &= occupancy;
+            color = !color;
+            // Capturing with the king into a square still defended, possibly
+            // through the king's own square, is illegal and takes no part
+            if pt == KING && !(attackers & self.occupancy(&color)).is_empty() {
+                depth -= 1;
+                break;
+            }
+        }
+
+        while depth > 0 {
+            gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+            depth -= 1;
+        }
+        gains[0]
+    }
+
+    // Whether the exchange started by m wins at least threshold, stops as soon
+    // as the outcome is settled
+    pub fn see_ge(&self, m: &Move, threshold: i32) -> bool {
+        if m.is_move_type(MOVE_CASTLING) {
+            return threshold <= 0;
+        }
+        // Balance from the point of view of the side to recapture
+        let mut balance = self.see_gain(m) - threshold;
+        if balance < 0 {
+            return false;
+        }
+        balance -= self.see_moved_value(m);
+        if balance >= 0 {
+            return true;
+        }
+
+        let to = m.to_square();
+        let mut occupancy = self.see_occupancy(m);
+        let mut attackers = self.attackers_to(&to, &occupancy) & occupancy;
+        let mut color = THEM;
+        while let Some((pt, sq)) = self.least_valuable_attacker(&attackers, &color) {
+            occupancy ^= Bitboard::from(&sq);
+            attackers |= self.see_xrays(&to, &pt, &occupancy);
+            attackers &= occupancy;
+            color = !color;
+            balance = -balance - 1 - SEE_PIECE_VALUES[pt.0 as usize];
+            if balance >= 0 {
+                // A king capture into a still defended square is illegal, so the
+                // exchange goes to the other side after all
+                if pt == KING && !(attackers & self.occupancy(&color)).is_empty() {
+                    color = !color;
+                }
+                break;
+            }
+        }
+        // The side left to move at the end is the one losing the exchange
+        color != US
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn see(fen: &str, uci: &str) -> i32 {
+        let pos = Position::from(fen);
+        let m = pos.parse_uci_move(uci).unwrap();
+        let value = pos.see(&m);
+        assert!(pos.see_ge(&m, value), "see_ge({}) disagrees with see for {} in {}", value, uci, fen);
+        assert!(!pos.see_ge(&m, value + 1), "see_ge({}) disagrees with see for {} in {}", value + 1, uci, fen);
+        value
+    }
+
+    #[test]
+    fn undefended_capture() {
+        assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+    }
+
+    #[test]
+    fn rook_takes_pawn_defended_by_pawn() {
+        assert_eq!(see("4k3/8/3p4/4p3/8/8/8/4R1K1 w - - 0 1", "e1e5"), -400);
+    }
+
+    #[test]
+    fn xray_battery_on_a_file() {
+        assert_eq!(see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"), -200);
+        assert_eq!(see("3r3k/3r4/2n1n3/8/3p4/2PR4/1B1Q4/3R3K w - - 0 1", "d3d4"), -100);
+        assert_eq!(see("4k3/8/8/3p4/8/8/3R4/3R2K1 w - - 0 1", "d2d5"), 100);
+        assert_eq!(see("3rk3/3r4/8/3p4/8/8/3R4/3R2K1 w - - 0 1", "d2d5"), -400);
+        assert_eq!(see("3rk3/3r4/8/3p4/8/8/3R4/3Q2K1 w - - 0 1", "d2d5"), -400);
+    }
+
+    #[test]
+    fn enpassant() {
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 100);
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 0);
+    }
+
+    #[test]
+    fn promotion() {
+        assert_eq!(see("1r2k3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7c8q"), -100);
+        assert_eq!(see("1r2k3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7b8q"), 1300);
+        assert_eq!(see("4k3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7c8q"), 800);
+    }
+
+    #[test]
+    fn king_recaptures() {
+        // The king may take back only while nothing defends the square
+        assert_eq!(see("4k3/8/8/8/8/8/3pK3/8 b - - 0 1", "d2d1q"), -100);
+        assert_eq!(see("4k3/8/8/3p4/4K3/8/8/8 b - - 0 1", "d5d4"), -100);
+        assert_eq!(see("4k3/8/8/3n4/4K3/8/8/3R4 w - - 0 1", "d1d5"), 300);
+        assert_eq!(see("4k3/3r4/8/3n4/4K3/8/8/3R4 w - - 0 1", "d1d5"), 300);
+        assert_eq!(see("4k3/1b1r4/8/3n4/4K3/8/8/3R4 w - - 0 1", "d1d5"), -200);
+    }
+
+    #[test]
+    fn king_reveals_slider_behind_it() {
+        // After Nxd4 Nxd4 the king on e4 cannot take back, it would step onto the
+        // rook's line. The move does not address the check, so it is built by hand.
+        let pos = Position::from("7k/8/8/5n2/3pK2r/1N6/8/8 w - - 0 1");
+        let from = Square::try_from("b3").unwrap();
+        let to = Square::try_from("d4").unwrap();
+        let m = Move::capture(&from, &to, (PAWN.0 as u32) << CAP_SHIFT);
+        assert_eq!(pos.see(&m), -200);
+        assert!(!pos.see_ge(&m, 0));
+        assert!(pos.see_ge(&m, -200));
+    }
+
+    #[test]
+    fn castling_is_neutral() {
+        assert_eq!(see("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1"), 0);
+    }
+
+    #[test]
+    fn see_ge_agrees_with_see() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        ];
+        for fen in fens.iter() {
+            let pos = Position::from(*fen);
+            for m in pos.legal_moves().iter() {
+                let value = pos.see(m);
+                for threshold in [-900, -300, -100, 0, 1, 100, 300, 900].iter() {
+                    assert_eq!(pos.see_ge(m, *threshold), value >= *threshold, "{} {}", fen, pos.move_to_uci(m));
+                }
+            }
+        }
+    }
+}
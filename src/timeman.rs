@@ -0,0 +1,168 @@
+use std::time::{Duration, Instant};
+
+use crate::constants::*;
+use crate::position::Position;
+use crate::search::SearchLimits;
+use crate::types::Move;
+
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+pub const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+// Moves still to be played in a sudden-death game, shrinking as the game goes
+// on but never below this
+const MIN_MOVES_LEFT: u32 = 20;
+const EXPECTED_GAME_LENGTH: u32 = 60;
+
+// Even a nearly flagged clock leaves the hard limit a few milliseconds, the
+// first iteration always completes anyway
+const MIN_HARD_LIMIT_MS: u64 = 5;
+
+// Soft limit is the target the search aims for between iterations, the hard
+// limit aborts the search in the middle of an iteration
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    // Decayed count of best move changes between iterations
+    best_move_changes: f64,
+    previous_score: Option<i32>,
+    previous_best_move: Option<Move>,
+}
+
+impl TimeManager {
+    pub fn new(pos: &Position, limits: &SearchLimits) -> TimeManager {
+        let (soft_limit, hard_limit) = match limits_ms(pos, limits) {
+            Some((soft, hard)) => (Some(Duration::from_millis(soft)), Some(Duration::from_millis(hard))),
+            None => (None, None),
+        };
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            best_move_changes: 0.0,
+            previous_score: None,
+            previous_best_move: None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|hard_limit| self.start.elapsed() >= hard_limit)
+    }
+
+    // Called after every completed iteration. The soft limit is stretched while
+    // the best move keeps changing or the score falls, never beyond the hard limit.
+    pub fn iteration_done(&mut self, best_move: &Move, score: i32) -> bool {
+        self.best_move_changes /= 2.0;
+        if self.previous_best_move.is_some_and(|previous| previous != *best_move) {
+            self.best_move_changes += 1.0;
+        }
+        let score_drop = self.previous_score.map_or(0, |previous| (previous - score).clamp(0, 100));
+        self.previous_best_move = Some(*best_move);
+        self.previous_score = Some(score);
+
+        let (soft_limit, hard_limit) = match (self.soft_limit, self.hard_limit) {
+            (Some(soft_limit), Some(hard_limit)) => (soft_limit, hard_limit),
+            _ => return false,
+        };
+        let instability = 1.0 + self.best_move_changes / 2.0;
+        let falling_score = 1.0 + score_drop as f64 / 100.0;
+        let target = soft_limit.mul_f64(instability * falling_score).min(hard_limit);
+        self.start.elapsed() >= target
+    }
+}
+
+// Soft and hard limits in milliseconds, none when the search is not timed
+fn limits_ms(pos: &Position, limits: &SearchLimits) -> Option<(u64, u64)> {
+    if limits.infinite {
+        return None;
+    }
+    if let Some(movetime) = limits.movetime {
+        let movetime = movetime.saturating_sub(limits.move_overhead).max(1);
+        return Some((movetime, movetime));
+    }
+    let (time, inc) = if pos.side_to_move() == WHITE {
+        (limits.wtime?, limits.winc.unwrap_or(0))
+    } else {
+        (limits.btime?, limits.binc.unwrap_or(0))
+    };
+
+    let available = time.saturating_sub(limits.move_overhead).max(1);
+    let moves_left = match limits.movestogo {
+        Some(movestogo) => movestogo.max(1),
+        None => EXPECTED_GAME_LENGTH.saturating_sub(pos.fullmoves()).max(MIN_MOVES_LEFT),
+    } as u64;
+    let hard = (available * 4 / 5).max(MIN_HARD_LIMIT_MS);
+    let soft = (available / moves_left + inc * 3 / 4).min(hard);
+    Some((soft, hard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::SearchLimits;
+
+    fn clock(wtime: u64, winc: u64) -> SearchLimits {
+        SearchLimits {
+            wtime: Some(wtime),
+            winc: Some(winc),
+            move_overhead: DEFAULT_MOVE_OVERHEAD_MS,
+            ..SearchLimits::default()
+        }
+    }
+
+    #[test]
+    fn untimed_searches() {
+        let pos = Position::from(INITIAL_FEN);
+        let infinite = SearchLimits {
+            infinite: true,
+            wtime: Some(1000),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits_ms(&pos, &infinite), None);
+        assert_eq!(limits_ms(&pos, &SearchLimits::default()), None);
+        // Only the clock of the side to move counts
+        let black_clock = SearchLimits {
+            btime: Some(1000),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits_ms(&pos, &black_clock), None);
+    }
+
+    #[test]
+    fn movetime_leaves_the_overhead() {
+        let pos = Position::from(INITIAL_FEN);
+        let limits = SearchLimits {
+            movetime: Some(1000),
+            move_overhead: 50,
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits_ms(&pos, &limits), Some((950, 950)));
+    }
+
+    #[test]
+    fn clock_is_shared_over_the_moves_left() {
+        let pos = Position::from(INITIAL_FEN);
+        // 59 moves expected at move 1, the hard limit keeps a fifth of the clock
+        assert_eq!(limits_ms(&pos, &clock(59_010, 0)), Some((1000, 47_200)));
+        // Three quarters of the increment is added
+        assert_eq!(limits_ms(&pos, &clock(59_010, 400)), Some((1300, 47_200)));
+        let limits = SearchLimits {
+            movestogo: Some(10),
+            ..clock(10_010, 0)
+        };
+        assert_eq!(limits_ms(&pos, &limits), Some((1000, 8000)));
+    }
+
+    #[test]
+    fn low_clock() {
+        let pos = Position::from(INITIAL_FEN);
+        // Less than the overhead left, the hard limit still gives a few milliseconds
+        assert_eq!(limits_ms(&pos, &clock(5, 0)), Some((0, MIN_HARD_LIMIT_MS)));
+        // The soft limit never passes the hard one, whatever the increment
+        assert_eq!(limits_ms(&pos, &clock(100, 1000)), Some((72, 72)));
+    }
+}
@@ -0,0 +1,306 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::search::MATE_BOUND;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 65536;
+
+const ENTRIES_PER_BUCKET: usize = 4;
+const GENERATION_MASK: u8 = 0x3f;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TTEntry {
+    pub best_move: u16,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+// Entries are stored as two words with the key XOR-ed with the data, so a torn
+// write from another thread shows up as a key mismatch instead of bad data
+#[derive(Default)]
+struct AtomicEntry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// 0-15 bits - compressed best move
+// 16-31 bits - score
+// 32-39 bits - depth
+// 40-41 bits - bound, 0 for an empty entry
+// 42-47 bits - generation
+fn pack(entry: &TTEntry, generation: u8) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    (entry.best_move as u64)
+        | ((entry.score as i16 as u16 as u64) << 16)
+        | ((entry.depth.clamp(0, 255) as u64) << 32)
+        | (bound << 40)
+        | (((generation & GENERATION_MASK) as u64) << 42)
+}
+
+fn unpack(data: u64) -> Option<TTEntry> {
+    let bound = match (data >> 40) & 3 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        3 => Bound::Upper,
+        _ => return None,
+    };
+    Some(TTEntry {
+        best_move: data as u16,
+        score: (data >> 16) as u16 as i16 as i32,
+        depth: ((data >> 32) & 0xff) as i32,
+        bound,
+    })
+}
+
+fn data_generation(data: u64) -> u8 {
+    ((data >> 42) as u8) & GENERATION_MASK
+}
+
+fn data_depth(data: u64) -> i32 {
+    ((data >> 32) & 0xff) as i32
+}
+
+// One cache line per bucket
+#[repr(align(64))]
+#[derive(Default)]
+struct Bucket {
+    entries: [AtomicEntry; ENTRIES_PER_BUCKET],
+}
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let bucket_count = (size_mb.max(1) << 20) / std::mem::size_of::<Bucket>();
+        TranspositionTable {
+            buckets: (0..bucket_count).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for bucket in self.buckets.iter() {
+            for entry in bucket.entries.iter() {
+                entry.key.store(0, Ordering::Relaxed);
+                entry.data.store(0, Ordering::Relaxed);
+            }
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    // Called once per search so entries from older searches are replaced first
+    pub fn new_search(&self) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation.store(generation.wrapping_add(1) & GENERATION_MASK, Ordering::Relaxed);
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        let idx = ((key as u128 * self.buckets.len() as u128) >> 64) as usize;
+        &self.buckets[idx]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        for entry in self.bucket(key).entries.iter() {
+            let data = entry.data.load(Ordering::Relaxed);
+            if entry.key.load(Ordering::Relaxed) ^ data == key {
+                return unpack(data);
+            }
+        }
+        None
+    }
+
+    // Overwrites the entry for the same key, otherwise the shallowest and oldest one
+    pub fn store(&self, key: u64, entry: &TTEntry) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+        let mut replace_idx = 0;
+        let mut replace_value = i32::MAX;
+        for (i, slot) in bucket.entries.iter().enumerate() {
+            let data = slot.data.load(Ordering::Relaxed);
+            if slot.key.load(Ordering::Relaxed) ^ data == key || unpack(data).is_none() {
+                replace_idx = i;
+                break;
+            }
+            let age = (generation.wrapping_sub(data_generation(data)) & GENERATION_MASK) as i32;
+            let value = data_depth(data) - 8 * age;
+            if value < replace_value {
+                replace_value = value;
+                replace_idx = i;
+            }
+        }
+
+        let slot = &bucket.entries[replace_idx];
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let mut new_entry = *entry;
+        if slot.key.load(Ordering::Relaxed) ^ old_data == key {
+            // Keep a deeper result from this search unless the new one is exact
+            if entry.bound != Bound::Exact
+                && data_generation(old_data) == generation
+                && data_depth(old_data) > entry.depth + 2
+            {
+                return;
+            }
+            // Preserve the old best move when the new entry has none
+            if new_entry.best_move == 0 {
+                new_entry.best_move = old_data as u16;
+            }
+        }
+        let data = pack(&new_entry, generation);
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // Permille of sampled entries written during the current search
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = self.buckets.iter().take(1000 / ENTRIES_PER_BUCKET);
+        let mut used = 0;
+        let mut total = 0;
+        for bucket in sample {
+            for entry in bucket.entries.iter() {
+                let data = entry.data.load(Ordering::Relaxed);
+                if unpack(data).is_some() && data_generation(data) == generation {
+                    used += 1;
+                }
+                total += 1;
+            }
+        }
+        used * 1000 / total.max(1)
+    }
+}
+
+// Mate scores are stored relative to the node rather than the root
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    fn entry(best_move: u16, depth: i32, bound: Bound) -> TTEntry {
+        TTEntry {
+            best_move,
+            score: 25,
+            depth,
+            bound,
+        }
+    }
+
+    // With 1 MB every key below 2^50 falls into the first bucket
+    fn key_in_bucket(bucket: u64, n: u64) -> u64 {
+        (bucket << 50) | n
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        assert!(tt.probe(1).is_none());
+        tt.store(1, &TTEntry { score: -MATE_BOUND, ..entry(0x1234, 7, Bound::Lower) });
+        let found = tt.probe(1).unwrap();
+        assert_eq!(found.best_move, 0x1234);
+        assert_eq!(found.score, -MATE_BOUND);
+        assert_eq!(found.depth, 7);
+        assert_eq!(found.bound, Bound::Lower);
+        assert!(tt.probe(2).is_none());
+        tt.clear();
+        assert!(tt.probe(1).is_none());
+    }
+
+    #[test]
+    fn same_key_keeps_deeper_result() {
+        let tt = TranspositionTable::new(1);
+        tt.store(1, &entry(0x1234, 10, Bound::Lower));
+        tt.store(1, &entry(0x5678, 2, Bound::Upper));
+        assert_eq!(tt.probe(1).unwrap().depth, 10);
+        // Exact results always go in, a missing best move keeps the old one
+        tt.store(1, &entry(0, 2, Bound::Exact));
+        let found = tt.probe(1).unwrap();
+        assert_eq!((found.best_move, found.depth, found.bound), (0x1234, 2, Bound::Exact));
+        // A later search may overwrite whatever depth
+        tt.new_search();
+        tt.store(1, &entry(0x5678, 1, Bound::Upper));
+        assert_eq!(tt.probe(1).unwrap().best_move, 0x5678);
+    }
+
+    #[test]
+    fn full_bucket_replaces_shallowest_entry() {
+        let tt = TranspositionTable::new(1);
+        for n in 1..=4 {
+            tt.store(key_in_bucket(0, n), &entry(n as u16, n as i32 * 2, Bound::Exact));
+        }
+        tt.store(key_in_bucket(0, 5), &entry(5, 5, Bound::Exact));
+        assert!(tt.probe(key_in_bucket(0, 1)).is_none());
+        for n in 2..=5 {
+            assert!(tt.probe(key_in_bucket(0, n)).is_some());
+        }
+    }
+
+    #[test]
+    fn full_bucket_replaces_older_entries_first() {
+        let tt = TranspositionTable::new(1);
+        for n in 1..=4 {
+            tt.store(key_in_bucket(0, n), &entry(n as u16, 20, Bound::Exact));
+            tt.new_search();
+        }
+        tt.store(key_in_bucket(0, 5), &entry(5, 1, Bound::Exact));
+        assert!(tt.probe(key_in_bucket(0, 1)).is_none());
+        assert!(tt.probe(key_in_bucket(0, 5)).is_some());
+    }
+
+    #[test]
+    fn hashfull_counts_the_current_search() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for bucket in 0..125 {
+            tt.store(key_in_bucket(bucket, 1), &entry(1, 1, Bound::Exact));
+            tt.store(key_in_bucket(bucket, 2), &entry(1, 1, Bound::Exact));
+        }
+        assert_eq!(tt.hashfull(), 250);
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_node() {
+        // A mate 5 plies from the root is 2 plies away from a node at ply 3
+        assert_eq!(score_to_tt(MATE - 5, 3), MATE - 2);
+        assert_eq!(score_from_tt(MATE - 2, 3), MATE - 5);
+        assert_eq!(score_to_tt(-MATE + 5, 3), -MATE + 2);
+        assert_eq!(score_from_tt(-MATE + 2, 3), -MATE + 5);
+        assert_eq!(score_to_tt(150, 3), 150);
+        assert_eq!(score_from_tt(-150, 3), -150);
+    }
+}
@@ -15,6 +15,20 @@ impl ops::BitXorAssign<CastlingRights> for CastlingRights {
     }
 }
 
+impl ops::BitOr<CastlingRights> for CastlingRights {
+    type Output = CastlingRights;
+
+    fn bitor(self, rhs: CastlingRights) -> Self::Output {
+        CastlingRights(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign<CastlingRights> for CastlingRights {
+    fn bitor_assign(&mut self, rhs: CastlingRights) {
+        self.0 |= rhs.0;
+    }
+}
+
 impl ops::Add<Square> for Square {
     type Output = Square;
 
@@ -85,6 +99,30 @@ impl ops::BitXorAssign<Bitboard> for Bitboard {
     }
 }
 
+impl ops::Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Self::Output {
+        Bitboard(!self.0)
+    }
+}
+
+impl ops::Shl<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        Bitboard(self.0 << rhs)
+    }
+}
+
+impl ops::Shr<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shr(self, rhs: u32) -> Self::Output {
+        Bitboard(self.0 >> rhs)
+    }
+}
+
 impl ops::Not for Color {
     type Output = Color;
 
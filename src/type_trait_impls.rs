@@ -1,29 +1,37 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
 use crate::constants::*;
 use crate::type_traits::Printable;
-use crate::types::{Bitboard, CastlingRights, Color, ColoredPiece, File, PieceType, Rank, Square};
+use crate::types::{
+    Bitboard, CastlingRights, Color, ColoredPiece, FenError, File, GameResult, MoveError, PgnError, PieceType, Rank, Square,
+    Violation,
+};
 
 impl From<&u8> for Bitboard {
     fn from(sq: &u8) -> Self {
-        Bitboard((1 as u64) << sq)
+        Bitboard(1_u64 << sq)
     }
 }
 
 impl From<&Square> for Bitboard {
     fn from(sq: &Square) -> Self {
-        Bitboard((1 as u64) << sq.0)
+        Bitboard(1_u64 << sq.0)
     }
 }
 
-impl From<&char> for CastlingRights {
-    fn from(c: &char) -> Self {
-        match *c {
-            'k' => BLACK_KING_SIDE,
-            'q' => BLACK_QUEEN_SIDE,
-            'K' => WHITE_KING_SIDE,
-            'Q' => WHITE_QUEEN_SIDE,
-            '-' => CASTLING_RIGHT_NONE,
-            _ => panic!("Unknown castling right!"),
+// Pops squares from the least significant bit upwards
+impl Iterator for Bitboard {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
         }
+        let sq = Square(self.lsb() as u8);
+        self.0 &= self.0 - 1;
+        Some(sq)
     }
 }
 
@@ -40,12 +48,25 @@ impl From<&CastlingRights> for char {
     }
 }
 
-impl From<&char> for Color {
-    fn from(c: &char) -> Self {
-        match *c {
-            'w' => WHITE,
-            'b' => BLACK,
-            _ => panic!("Unknown color!"),
+impl From<&CastlingRights> for String {
+    fn from(cr: &CastlingRights) -> Self {
+        let rights: String = [WHITE_KING_SIDE, WHITE_QUEEN_SIDE, BLACK_KING_SIDE, BLACK_QUEEN_SIDE]
+            .iter()
+            .filter(|right| cr.allows(**right))
+            .map(char::from)
+            .collect();
+        if rights.is_empty() { String::from("-") } else { rights }
+    }
+}
+
+impl TryFrom<&str> for Color {
+    type Error = FenError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "w" => Ok(WHITE),
+            "b" => Ok(BLACK),
+            _ => Err(FenError::InvalidSideToMove(String::from(s))),
         }
     }
 }
@@ -60,17 +81,19 @@ impl From<&Color> for char {
     }
 }
 
-impl From<&char> for PieceType {
-    fn from(c: &char) -> Self {
+impl TryFrom<&char> for PieceType {
+    type Error = FenError;
+
+    fn try_from(c: &char) -> Result<Self, Self::Error> {
         let c_lower = c.to_ascii_lowercase();
         match c_lower {
-            'p' => PAWN,
-            'n' => KNIGHT,
-            'b' => BISHOP,
-            'r' => ROOK,
-            'q' => QUEEN,
-            'k' => KING,
-            _ => panic!("Unknown piece type!"),
+            'p' => Ok(PAWN),
+            'n' => Ok(KNIGHT),
+            'b' => Ok(BISHOP),
+            'r' => Ok(ROOK),
+            'q' => Ok(QUEEN),
+            'k' => Ok(KING),
+            _ => Err(FenError::InvalidPiece(*c)),
         }
     }
 }
@@ -89,11 +112,13 @@ impl From<&PieceType> for char {
     }
 }
 
-impl From<&char> for ColoredPiece {
-    fn from(c: &char) -> Self {
+impl TryFrom<&char> for ColoredPiece {
+    type Error = FenError;
+
+    fn try_from(c: &char) -> Result<Self, Self::Error> {
         let color = if c.is_uppercase() { WHITE } else { BLACK };
-        let piece_type = PieceType::from(c);
-        ColoredPiece(piece_type, color)
+        let piece_type = PieceType::try_from(c)?;
+        Ok(ColoredPiece(piece_type, color))
     }
 }
 
@@ -104,18 +129,20 @@ impl From<&ColoredPiece> for char {
     }
 }
 
-impl From<&char> for File {
-    fn from(f: &char) -> Self {
+impl TryFrom<&char> for File {
+    type Error = ();
+
+    fn try_from(f: &char) -> Result<Self, Self::Error> {
         match *f {
-            'a' => FILE_A,
-            'b' => FILE_B,
-            'c' => FILE_C,
-            'd' => FILE_D,
-            'e' => FILE_E,
-            'f' => FILE_F,
-            'g' => FILE_G,
-            'h' => FILE_H,
-            _ => panic!("Unknown file!"),
+            'a' => Ok(FILE_A),
+            'b' => Ok(FILE_B),
+            'c' => Ok(FILE_C),
+            'd' => Ok(FILE_D),
+            'e' => Ok(FILE_E),
+            'f' => Ok(FILE_F),
+            'g' => Ok(FILE_G),
+            'h' => Ok(FILE_H),
+            _ => Err(()),
         }
     }
 }
@@ -136,18 +163,20 @@ impl From<&File> for char {
     }
 }
 
-impl From<&char> for Rank {
-    fn from(f: &char) -> Self {
+impl TryFrom<&char> for Rank {
+    type Error = ();
+
+    fn try_from(f: &char) -> Result<Self, Self::Error> {
         match *f {
-            '1' => RANK_1,
-            '2' => RANK_2,
-            '3' => RANK_3,
-            '4' => RANK_4,
-            '5' => RANK_5,
-            '6' => RANK_6,
-            '7' => RANK_7,
-            '8' => RANK_8,
-            _ => panic!("Unknown rank!"),
+            '1' => Ok(RANK_1),
+            '2' => Ok(RANK_2),
+            '3' => Ok(RANK_3),
+            '4' => Ok(RANK_4),
+            '5' => Ok(RANK_5),
+            '6' => Ok(RANK_6),
+            '7' => Ok(RANK_7),
+            '8' => Ok(RANK_8),
+            _ => Err(()),
         }
     }
 }
@@ -168,16 +197,21 @@ impl From<&Rank> for char {
     }
 }
 
-impl From<&String> for Square {
-    fn from(s: &String) -> Self {
-        if *s == "-" {
-            SQUARE_INVALID
-        } else {
-            let mut s = s.chars();
-            let file = File(s.next().unwrap() as u8 - 'a' as u8);
-            let rank = Rank(s.next().unwrap() as u8 - '1' as u8);
-            Square::new(&file, &rank)
+impl TryFrom<&str> for Square {
+    type Error = FenError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        if s == "-" {
+            return Ok(SQUARE_INVALID);
+        }
+        let error = || FenError::InvalidEnpassantSquare(String::from(s));
+        let chars: Vec<char> = s.chars().collect();
+        if chars.len() != 2 {
+            return Err(error());
         }
+        let file = File::try_from(&chars[0]).map_err(|_| error())?;
+        let rank = Rank::try_from(&chars[1]).map_err(|_| error())?;
+        Ok(Square::new(&file, &rank))
     }
 }
 
@@ -193,29 +227,101 @@ impl From<&Square> for String {
     }
 }
 
-impl Printable for CastlingRights {
-    fn print(&self) {
-        let mut no_castling = true;
-        if self.allows(WHITE_KING_SIDE) {
-            print!("{}", char::from(&WHITE_KING_SIDE));
-            no_castling = false;
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::InvalidRankLength(rank) => write!(f, "rank {} does not have 8 squares", char::from(rank)),
+            FenError::TooManyRanks => write!(f, "more than 8 ranks"),
+            FenError::TooFewRanks => write!(f, "fewer than 8 ranks"),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastlingRight(c) => write!(f, "invalid castling right '{}'", c),
+            FenError::InvalidEnpassantSquare(s) => write!(f, "invalid en-passant square '{}'", s),
+            FenError::InvalidHalfmoves(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoves(s) => write!(f, "invalid fullmove number '{}'", s),
+            FenError::MissingField(stage) => write!(f, "missing {:?} field", stage),
+        }
+    }
+}
+
+impl Error for FenError {}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::InvalidSquare(s) => write!(f, "invalid square '{}'", s),
+            MoveError::InvalidPromotion(c) => write!(f, "invalid promotion piece '{}'", c),
+            MoveError::NoPieceOnSquare(s) => write!(f, "no piece of the side to move on {}", s),
+            MoveError::IllegalMove(s) => write!(f, "illegal move '{}'", s),
+            MoveError::AmbiguousMove(s) => write!(f, "ambiguous move '{}'", s),
         }
-        if self.allows(WHITE_QUEEN_SIDE) {
-            print!("{}", char::from(&WHITE_QUEEN_SIDE));
-            no_castling = false;
+    }
+}
+
+impl Error for MoveError {}
+
+impl From<&str> for GameResult {
+    fn from(s: &str) -> Self {
+        match s {
+            "1-0" => GameResult::WhiteWins,
+            "0-1" => GameResult::BlackWins,
+            "1/2-1/2" => GameResult::Draw,
+            _ => GameResult::Unknown,
         }
-        if self.allows(BLACK_KING_SIDE) {
-            print!("{}", char::from(&BLACK_KING_SIDE));
-            no_castling = false;
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameResult::WhiteWins => write!(f, "1-0"),
+            GameResult::BlackWins => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+            GameResult::Unknown => write!(f, "*"),
         }
-        if self.allows(BLACK_QUEEN_SIDE) {
-            print!("{}", char::from(&BLACK_QUEEN_SIDE));
-            no_castling = false;
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(s) => write!(f, "invalid tag pair '{}'", s),
+            PgnError::UnterminatedTag => write!(f, "unterminated tag pair"),
+            PgnError::UnterminatedComment => write!(f, "unterminated comment"),
+            PgnError::UnterminatedVariation => write!(f, "unterminated variation"),
+            PgnError::UnexpectedVariationEnd => write!(f, "')' without an open variation"),
+            PgnError::VariationWithoutMove => write!(f, "variation before any move"),
+            PgnError::InvalidFen(err) => write!(f, "invalid FEN tag: {}", err),
+            PgnError::InvalidPosition(violations) => {
+                let reasons: Vec<String> = violations.iter().map(|violation| violation.to_string()).collect();
+                write!(f, "invalid FEN tag position: {}", reasons.join(", "))
+            }
+            PgnError::InvalidMove(san, err) => write!(f, "invalid move '{}': {}", san, err),
         }
-        if no_castling {
-            print!("-");
+    }
+}
+
+impl Error for PgnError {}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingKing(color) => write!(f, "side '{}' has no king", char::from(color)),
+            Violation::TooManyKings(color) => write!(f, "side '{}' has more than one king", char::from(color)),
+            Violation::TooManyPawns(color) => write!(f, "side '{}' has more than 8 pawns", char::from(color)),
+            Violation::TooManyPieces(color) => write!(f, "side '{}' has more than 16 pieces", char::from(color)),
+            Violation::PawnOnBackRank(sq) => write!(f, "pawn on back rank square {}", String::from(sq)),
+            Violation::CastlingWithoutKing(cr) => write!(f, "castling right {} without king on its home square", String::from(cr)),
+            Violation::CastlingWithoutRook(cr) => write!(f, "castling right {} without rook on its home square", String::from(cr)),
+            Violation::InvalidEnpassantSquare(sq) => write!(f, "en-passant square {} not behind a pushed pawn", String::from(sq)),
+            Violation::OpponentInCheck => write!(f, "side not to move is in check"),
         }
-        println!();
+    }
+}
+
+impl Printable for CastlingRights {
+    fn print(&self) {
+        println!("{}", String::from(self));
     }
 }
 
@@ -246,7 +352,7 @@ impl Printable for ColoredPiece {
 impl Printable for Bitboard {
     fn print(&self) {
         for shift in 0..64 {
-            let bit = (1 as u64) << (shift ^ 56);
+            let bit = 1_u64 << (shift ^ 56);
             if shift != 0 && shift % 8 == 0 {
                 println!();
             }
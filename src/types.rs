@@ -1,27 +1,27 @@
-use crate::constants::{CAP_SHIFT, MOVE_CAPTURE, MOVE_CASTLING, MOVE_DOUBLE_PUSH, MOVE_ENPASSANT, MOVE_NORMAL, MOVE_PROM_CAP, MOVE_PROMOTION, PROM_SHIFT};
+use crate::constants::{FILE_A_BB, FILE_H_BB, CAP_SHIFT, MOVE_CAPTURE, MOVE_CASTLING, MOVE_DOUBLE_PUSH, MOVE_ENPASSANT, MOVE_NORMAL, MOVE_PROM_CAP, MOVE_PROMOTION, PROM_SHIFT};
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct Rank(pub u8);
 
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub struct File(pub u8);
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct Square(pub u8);
 
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub struct PieceType(pub u8);
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct Color(pub u8);
 
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub struct ColoredPiece(pub PieceType, pub Color);
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct CastlingRights(pub u8);
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct Bitboard(pub u64);
 
 // 0-5 bits - encoded from square
@@ -29,10 +29,10 @@ pub struct Bitboard(pub u64);
 // 12-17 bits - flags for move type
 // 18-20 bits - encoded promotion type
 // 21-23 bits - encoded captured piece type
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct Move(pub u32);
 
-#[derive(Eq, PartialEq, Copy, Clone, Ord, PartialOrd)]
+#[derive(Eq, PartialEq, Copy, Clone, Ord, PartialOrd, Debug)]
 pub enum FenStage {
     Pieces,
     SideToMove,
@@ -43,6 +43,65 @@ pub enum FenStage {
     Done,
 }
 
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum FenError {
+    InvalidPiece(char),
+    InvalidRankLength(Rank),
+    TooManyRanks,
+    TooFewRanks,
+    InvalidSideToMove(String),
+    InvalidCastlingRight(char),
+    InvalidEnpassantSquare(String),
+    InvalidHalfmoves(String),
+    InvalidFullmoves(String),
+    MissingField(FenStage),
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum MoveError {
+    InvalidSquare(String),
+    InvalidPromotion(char),
+    NoPieceOnSquare(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum PgnError {
+    InvalidTag(String),
+    UnterminatedTag,
+    UnterminatedComment,
+    UnterminatedVariation,
+    UnexpectedVariationEnd,
+    VariationWithoutMove,
+    InvalidFen(FenError),
+    InvalidPosition(Vec<Violation>),
+    InvalidMove(String, MoveError),
+}
+
+// Reasons a syntactically valid position cannot occur in a game, squares and
+// colors refer to the real board
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Violation {
+    MissingKing(Color),
+    TooManyKings(Color),
+    TooManyPawns(Color),
+    TooManyPieces(Color),
+    PawnOnBackRank(Square),
+    CastlingWithoutKing(CastlingRights),
+    CastlingWithoutRook(CastlingRights),
+    InvalidEnpassantSquare(Square),
+    OpponentInCheck,
+}
+
 impl Move {
     pub fn normal(from: &Square, to: &Square) -> Move {
         Move((from.0 as u32) | ((to.0 as u32) << 6) | MOVE_NORMAL)
@@ -52,7 +111,7 @@ impl Move {
         Move((from.0 as u32) | ((to.0 as u32) << 6) | MOVE_CAPTURE | cap_type)
     }
 
-    pub fn promotion(from: &Square, to: &Square, prom_type: u32, cap_type: u32) -> Move {
+    pub fn promotion(from: &Square, to: &Square, prom_type: u32) -> Move {
         Move((from.0 as u32) | ((to.0 as u32) << 6) | MOVE_PROMOTION | prom_type)
     }
 
@@ -72,10 +131,12 @@ impl Move {
         Move((from.0 as u32) | ((to.0 as u32) << 6) | MOVE_ENPASSANT)
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn from_square(&self) -> Square {
         Square((self.0 & 0x3f) as u8)
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_square(&self) -> Square {
         Square(((self.0 >> 6) & 0x3f) as u8)
     }
@@ -91,6 +152,17 @@ impl Move {
     pub fn is_move_type(&self, move_type: u32) -> bool {
         (self.0 & move_type) != 0
     }
+
+    // 16-bit form holding only the squares and promotion type, the remaining
+    // flags can be recovered by matching against generated moves
+    pub fn compressed(&self) -> u16 {
+        let prom_bits = if self.is_move_type(MOVE_PROMOTION | MOVE_PROM_CAP) {
+            self.promotion_type().0 as u16
+        } else {
+            0
+        };
+        (self.0 & 0xfff) as u16 | (prom_bits << 12)
+    }
 }
 
 impl Bitboard {
@@ -98,9 +170,61 @@ impl Bitboard {
         Bitboard(self.0.swap_bytes())
     }
 
+    // Both ends are undefined for an empty board, callers check is_empty first
     pub fn lsb(&self) -> u32 {
+        debug_assert!(!self.is_empty());
         self.0.trailing_zeros()
     }
+
+    pub fn msb(&self) -> u32 {
+        debug_assert!(!self.is_empty());
+        63 - self.0.leading_zeros()
+    }
+
+    pub fn popcount(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, sq: &Square) -> bool {
+        self.0 & (1 << sq.0) != 0
+    }
+
+    // Directional shifts drop the squares that would wrap around to the other side of the board
+    pub fn north(&self) -> Bitboard {
+        Bitboard(self.0 << 8)
+    }
+
+    pub fn south(&self) -> Bitboard {
+        Bitboard(self.0 >> 8)
+    }
+
+    pub fn east(&self) -> Bitboard {
+        Bitboard((self.0 & !FILE_H_BB.0) << 1)
+    }
+
+    pub fn west(&self) -> Bitboard {
+        Bitboard((self.0 & !FILE_A_BB.0) >> 1)
+    }
+
+    pub fn north_east(&self) -> Bitboard {
+        Bitboard((self.0 & !FILE_H_BB.0) << 9)
+    }
+
+    pub fn north_west(&self) -> Bitboard {
+        Bitboard((self.0 & !FILE_A_BB.0) << 7)
+    }
+
+    pub fn south_east(&self) -> Bitboard {
+        Bitboard((self.0 & !FILE_H_BB.0) >> 7)
+    }
+
+    pub fn south_west(&self) -> Bitboard {
+        Bitboard((self.0 & !FILE_A_BB.0) >> 9)
+    }
 }
 
 impl Square {
@@ -125,4 +249,74 @@ impl CastlingRights {
     pub fn spoil(&mut self, cr: CastlingRights) {
         self.0 &= cr.0;
     }
+
+    // Removes the given rights, unlike spoil which keeps them
+    pub fn remove(&mut self, cr: CastlingRights) {
+        self.0 &= !cr.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+    use std::convert::TryFrom;
+
+    fn bb(squares: &[&str]) -> Bitboard {
+        squares.iter().fold(Bitboard(0), |bb, name| bb | Bitboard::from(&Square::try_from(*name).unwrap()))
+    }
+
+    #[test]
+    fn iteration_pops_lowest_square_first() {
+        let squares: Vec<Square> = bb(&["h8", "a1", "e4"]).collect();
+        assert_eq!(squares, vec![A1, Square::try_from("e4").unwrap(), H8]);
+        assert_eq!(Bitboard(0).next(), None);
+    }
+
+    #[test]
+    fn bit_queries() {
+        let board = bb(&["b1", "e4", "g8"]);
+        assert_eq!(board.popcount(), 3);
+        assert_eq!(board.lsb(), B1.0 as u32);
+        assert_eq!(board.msb(), G8.0 as u32);
+        assert!(board.contains(&B1));
+        assert!(!board.contains(&A1));
+        assert!(Bitboard(0).is_empty());
+        assert!(!board.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn lsb_of_empty_board() {
+        Bitboard(0).lsb();
+    }
+
+    #[test]
+    fn shifts_do_not_wrap() {
+        assert_eq!(bb(&["a4", "h4"]).east(), bb(&["b4"]));
+        assert_eq!(bb(&["a4", "h4"]).west(), bb(&["g4"]));
+        assert_eq!(bb(&["a4", "h4"]).north_east(), bb(&["b5"]));
+        assert_eq!(bb(&["a4", "h4"]).north_west(), bb(&["g5"]));
+        assert_eq!(bb(&["a4", "h4"]).south_east(), bb(&["b3"]));
+        assert_eq!(bb(&["a4", "h4"]).south_west(), bb(&["g3"]));
+        assert_eq!(bb(&["d8", "d1"]).north(), bb(&["d2"]));
+        assert_eq!(bb(&["d8", "d1"]).south(), bb(&["d7"]));
+    }
+
+    #[test]
+    fn operators_and_masks() {
+        assert_eq!(!Bitboard(0), Bitboard(u64::MAX));
+        assert_eq!(bb(&["a1"]) << 9, bb(&["b2"]));
+        assert_eq!(bb(&["b2"]) >> 9, bb(&["a1"]));
+        assert_eq!(RANK_1_BB, bb(&["a1", "b1", "c1", "d1", "e1", "f1", "g1", "h1"]));
+        assert_eq!(FILE_H_BB, bb(&["h1", "h2", "h3", "h4", "h5", "h6", "h7", "h8"]));
+        assert_eq!(FILE_BBS.iter().fold(Bitboard(0), |all, file| all | *file), Bitboard(u64::MAX));
+        assert_eq!(RANK_BBS.iter().fold(Bitboard(0), |all, rank| all | *rank), Bitboard(u64::MAX));
+        // Both long diagonals sit in the middle of their tables
+        assert_eq!(DIAGONAL_BBS[7], Bitboard(0x8040_2010_0804_0201));
+        assert_eq!(ANTI_DIAGONAL_BBS[7], Bitboard(0x0102_0408_1020_4080));
+        assert_eq!(DIAGONAL_BBS[0], bb(&["a8"]));
+        assert_eq!(ANTI_DIAGONAL_BBS[14], bb(&["h8"]));
+    }
 }
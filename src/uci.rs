@@ -0,0 +1,260 @@
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::constants::*;
+use crate::game::Game;
+use crate::position::Position;
+use crate::search::{self, SearchLimits, MAX_THREADS};
+use crate::timeman::{DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS};
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB};
+
+struct Uci {
+    // None once a position command was rejected, there is nothing to search
+    // until the GUI sends a valid one
+    game: Option<Game>,
+    stop: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
+    tt: Arc<TranspositionTable>,
+    chess960: bool,
+    threads: usize,
+    move_overhead: u64,
+}
+
+fn parse_value<T: std::str::FromStr>(tokens: &mut std::slice::Iter<&str>) -> Option<T> {
+    tokens.next().and_then(|token| token.parse::<T>().ok())
+}
+
+impl Uci {
+    fn new() -> Uci {
+        Uci {
+            game: Some(Game::new(Position::from(INITIAL_FEN))),
+            stop: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            chess960: false,
+            threads: 1,
+            move_overhead: DEFAULT_MOVE_OVERHEAD_MS,
+        }
+    }
+
+    fn handle_uci(&self) {
+        println!("id name Nirsyde {}", env!("CARGO_PKG_VERSION"));
+        println!("id author Manik Charan");
+        println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH_MB, MAX_HASH_MB);
+        println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+        println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS
+        );
+        println!("option name UCI_Chess960 type check default false");
+        println!("uciok");
+    }
+
+    // position [startpos | fen <fen>] [moves <move>...]
+    fn handle_position(&mut self, tokens: &[&str]) {
+        // The previous game must not survive a rejected command
+        self.game = None;
+        let moves_idx = tokens.iter().position(|token| *token == "moves").unwrap_or(tokens.len());
+        let mut position = match tokens.first() {
+            Some(&"startpos") => Position::from(INITIAL_FEN),
+            Some(&"fen") => match Position::from_fen(&tokens[1..moves_idx].join(" ")) {
+                Ok(position) => position,
+                Err(err) => {
+                    println!("info string invalid fen: {}", err);
+                    return;
+                }
+            },
+            _ => return,
+        };
+        // Shredder-FEN castling turns Chess960 on even without the option
+        if self.chess960 {
+            position.set_chess960(true);
+        }
+        let violations = position.validate();
+        if !violations.is_empty() {
+            for violation in violations.iter() {
+                println!("info string invalid position: {}", violation);
+            }
+            return;
+        }
+        let mut game = Game::new(position);
+        for name in tokens.iter().skip(moves_idx + 1) {
+            match game.position().parse_uci_move(name) {
+                Ok(m) => game.make_move(&m),
+                Err(err) => {
+                    println!("info string {}", err);
+                    return;
+                }
+            }
+        }
+        self.game = Some(game);
+    }
+
+    fn handle_go(&mut self, tokens: &[&str]) {
+        let game = match &self.game {
+            Some(game) => game.clone(),
+            None => {
+                println!("info string no valid position to search");
+                println!("bestmove 0000");
+                return;
+            }
+        };
+        let mut limits = SearchLimits {
+            move_overhead: self.move_overhead,
+            ..SearchLimits::default()
+        };
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            match *token {
+                "perft" => {
+                    game.position().divide(parse_value(&mut tokens).unwrap_or(1));
+                    return;
+                }
+                "depth" => limits.depth = parse_value(&mut tokens),
+                "movetime" => limits.movetime = parse_value(&mut tokens),
+                "wtime" => limits.wtime = parse_value(&mut tokens),
+                "btime" => limits.btime = parse_value(&mut tokens),
+                "winc" => limits.winc = parse_value(&mut tokens),
+                "binc" => limits.binc = parse_value(&mut tokens),
+                "movestogo" => limits.movestogo = parse_value(&mut tokens),
+                "infinite" => limits.infinite = true,
+                _ => {}
+            }
+        }
+
+        self.handle_stop();
+        self.stop.store(false, Ordering::SeqCst);
+        let stop = Arc::clone(&self.stop);
+        let tt = Arc::clone(&self.tt);
+        let threads = self.threads;
+        self.search_thread = Some(thread::spawn(move || {
+            search::search(&game, limits, threads, stop, tt);
+        }));
+    }
+
+    // Waits for a running search to print its best move
+    fn handle_stop(&mut self) {
+        if let Some(search_thread) = self.search_thread.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            search_thread.join().unwrap();
+        }
+    }
+
+    // setoption name <name> [value <value>]
+    fn handle_setoption(&mut self, tokens: &[&str]) {
+        let value_idx = tokens.iter().position(|token| *token == "value").unwrap_or(tokens.len());
+        let name = tokens.get(1..value_idx).map(|name| name.join(" ")).unwrap_or_default();
+        let value = tokens.get(value_idx + 1..).map(|value| value.join(" ")).unwrap_or_default();
+        self.handle_stop();
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(size_mb) => self.tt = Arc::new(TranspositionTable::new(size_mb.clamp(1, MAX_HASH_MB))),
+                Err(_) => println!("info string invalid Hash value {}", value),
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) => self.threads = threads.clamp(1, MAX_THREADS),
+                Err(_) => println!("info string invalid Threads value {}", value),
+            },
+            "move overhead" => match value.parse::<u64>() {
+                Ok(move_overhead) => self.move_overhead = move_overhead.min(MAX_MOVE_OVERHEAD_MS),
+                Err(_) => println!("info string invalid Move Overhead value {}", value),
+            },
+            "uci_chess960" => match value.parse::<bool>() {
+                Ok(chess960) => self.chess960 = chess960,
+                Err(_) => println!("info string invalid UCI_Chess960 value {}", value),
+            },
+            _ => println!("info string unknown option {}", name),
+        }
+    }
+
+    fn handle_ucinewgame(&mut self) {
+        self.handle_stop();
+        self.game = Some(Game::new(Position::from(INITIAL_FEN)));
+        self.tt.clear();
+    }
+}
+
+pub fn run() {
+    let mut uci = Uci::new();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => uci.handle_uci(),
+            Some(&"isready") => println!("readyok"),
+            Some(&"ucinewgame") => uci.handle_ucinewgame(),
+            Some(&"position") => uci.handle_position(&tokens[1..]),
+            Some(&"go") => uci.handle_go(&tokens[1..]),
+            Some(&"stop") => uci.handle_stop(),
+            Some(&"setoption") => uci.handle_setoption(&tokens[1..]),
+            Some(&"d") => {
+                if let Some(game) = &uci.game {
+                    game.position().print_parts();
+                }
+            }
+            Some(&"quit") => break,
+            _ => {}
+        }
+    }
+    uci.handle_stop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_with_moves() {
+        let mut uci = Uci::new();
+        uci.handle_position(&["startpos", "moves", "e2e4", "e7e5"]);
+        let position = *uci.game.expect("position should be accepted").position();
+        assert!(position.parse_uci_move("g1f3").is_ok());
+        assert!(position.parse_uci_move("e2e4").is_err());
+    }
+
+    #[test]
+    fn illegal_move_rejects_position() {
+        let mut uci = Uci::new();
+        uci.handle_position(&["startpos", "moves", "e2e4", "e2e4"]);
+        assert!(uci.game.is_none());
+        uci.handle_go(&["infinite"]);
+        assert!(uci.search_thread.is_none());
+
+        uci.handle_position(&["startpos"]);
+        uci.handle_go(&["infinite"]);
+        assert!(uci.search_thread.is_some());
+        uci.handle_stop();
+    }
+
+    #[test]
+    fn invalid_fen_rejects_position() {
+        let mut uci = Uci::new();
+        uci.handle_position(&["fen", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX", "w", "KQkq", "-", "0", "1"]);
+        assert!(uci.game.is_none());
+        uci.handle_go(&["infinite"]);
+        assert!(uci.search_thread.is_none());
+    }
+
+    #[test]
+    fn invalid_position_rejects_position() {
+        let mut uci = Uci::new();
+        uci.handle_position(&["fen", "r3k2r/8/8/8/8/8/8/R4K1R", "w", "KQkq", "-", "0", "1"]);
+        assert!(uci.game.is_none());
+        uci.handle_position(&["fen", "r3k2r/8/8/8/8/8/8/R4K1R", "w", "kq", "-", "0", "1"]);
+        assert!(uci.game.is_some());
+    }
+    #[test]
+    fn chess960_option() {
+        let mut uci = Uci::new();
+        uci.handle_setoption(&["name", "UCI_Chess960", "value", "true"]);
+        uci.handle_position(&["fen", "r3k2r/8/8/8/8/8/8/R4K1R", "w", "KQkq", "-", "0", "1", "moves", "f1h1"]);
+        let position = *uci.game.expect("position should be accepted").position();
+        assert!(position.to_fen().starts_with("r3k2r/8/8/8/8/8/8/R4RK1 b ha -"));
+    }
+}
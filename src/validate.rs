@@ -0,0 +1,145 @@
+use crate::constants::*;
+use crate::position::Position;
+use crate::types::{Square, Violation};
+
+impl Position {
+    // Lists every reason the position could not have arisen in a legal game,
+    // an empty list means the position is safe to search
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        // Oriented so that white pieces are at index 0 and squares are real
+        let real_pos = if self.side_to_move() == BLACK { self.flipped() } else { *self };
+
+        for color in COLORS.iter() {
+            let kings = real_pos.pieces_of(&KING, color).popcount();
+            if kings == 0 {
+                violations.push(Violation::MissingKing(*color));
+            } else if kings > 1 {
+                violations.push(Violation::TooManyKings(*color));
+            }
+            if real_pos.pieces_of(&PAWN, color).popcount() > 8 {
+                violations.push(Violation::TooManyPawns(*color));
+            }
+            if real_pos.occupancy(color).popcount() > 16 {
+                violations.push(Violation::TooManyPieces(*color));
+            }
+        }
+
+        for sq in real_pos.pieces(&PAWN) & (RANK_1_BB | RANK_8_BB) {
+            violations.push(Violation::PawnOnBackRank(sq));
+        }
+
+        let castling_rights = real_pos.castling_rights();
+        for (idx, right) in CASTLING_RIGHTS.iter().enumerate() {
+            if !castling_rights.allows(*right) {
+                continue;
+            }
+            let (color, back_rank, king_home) = if idx < 2 { (WHITE, RANK_1_BB, E1) } else { (BLACK, RANK_8_BB, E8) };
+            let kings = real_pos.pieces_of(&KING, &color) & back_rank;
+            if kings.is_empty() || (!real_pos.is_chess960() && !kings.contains(&king_home)) {
+                violations.push(Violation::CastlingWithoutKing(*right));
+                continue;
+            }
+            // The rook has to be on the side of the king the right is named after,
+            // and on its corner outside Chess960
+            let king_sq = Square(kings.lsb() as u8);
+            let rook_sq = real_pos.castling_rook(right);
+            let king_side = idx % 2 == 0;
+            if !real_pos.pieces_of(&ROOK, &color).contains(&rook_sq)
+                || rook_sq.rank() != king_sq.rank()
+                || (rook_sq.0 > king_sq.0) != king_side
+                || (!real_pos.is_chess960() && rook_sq != DEFAULT_CASTLING_ROOKS[idx])
+            {
+                violations.push(Violation::CastlingWithoutRook(*right));
+            }
+        }
+
+        // Internally the mover is always white, so a double-pushed enemy pawn
+        // sits on rank 5 with the two squares behind it empty
+        let ep_sq = self.enpassant_square();
+        if ep_sq != SQUARE_INVALID {
+            let occupied = self.occupied();
+            let pushed_ok = ep_sq.rank() == RANK_6
+                && self.pieces_of(&PAWN, &THEM).contains(&(ep_sq - Square(8)))
+                && !occupied.contains(&ep_sq)
+                && !occupied.contains(&(ep_sq + Square(8)));
+            if !pushed_ok {
+                let real_ep_sq = if self.side_to_move() == BLACK { Square(ep_sq.0 ^ 56) } else { ep_sq };
+                violations.push(Violation::InvalidEnpassantSquare(real_ep_sq));
+            }
+        }
+
+        if self.pieces_of(&KING, &THEM).popcount() == 1 && self.is_square_attacked(&self.king_square(&THEM), &US) {
+            violations.push(Violation::OpponentInCheck);
+        }
+
+        violations
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn violations(fen: &str) -> Vec<Violation> {
+        Position::from(fen).validate()
+    }
+
+    #[test]
+    fn suite_positions_are_valid() {
+        assert!(Position::from(INITIAL_FEN).is_valid());
+        assert!(Position::from("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1").is_valid());
+        assert!(Position::from("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").is_valid());
+    }
+
+    #[test]
+    fn kings_and_piece_counts() {
+        assert_eq!(
+            violations("8/8/8/8/8/8/8/8 w - - 0 1"),
+            vec![Violation::MissingKing(WHITE), Violation::MissingKing(BLACK)]
+        );
+        assert_eq!(violations("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"), vec![Violation::TooManyKings(WHITE)]);
+        assert_eq!(violations("4k3/pppppppp/p7/8/8/8/8/4K3 w - - 0 1"), vec![Violation::TooManyPawns(BLACK)]);
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        assert_eq!(
+            violations("4k2P/8/8/8/8/8/8/p3K3 b - - 0 1"),
+            vec![Violation::PawnOnBackRank(A1), Violation::PawnOnBackRank(H8)]
+        );
+    }
+
+    #[test]
+    fn castling_needs_king_and_rook_at_home() {
+        assert_eq!(
+            violations("r3k2r/8/8/8/8/8/8/R4K1R w KQkq - 0 1"),
+            vec![Violation::CastlingWithoutKing(WHITE_KING_SIDE), Violation::CastlingWithoutKing(WHITE_QUEEN_SIDE)]
+        );
+        assert_eq!(
+            violations("1r2k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"),
+            vec![Violation::CastlingWithoutRook(BLACK_QUEEN_SIDE)]
+        );
+    }
+
+    #[test]
+    fn enpassant_square_behind_pushed_pawn() {
+        assert_eq!(
+            violations("4k3/8/8/8/3p4/8/8/4K3 b - e3 0 1"),
+            vec![Violation::InvalidEnpassantSquare(Square::try_from("e3").unwrap())]
+        );
+        assert!(violations("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").is_empty());
+    }
+
+    #[test]
+    fn side_not_to_move_in_check() {
+        assert!(violations("4k3/8/8/8/8/8/8/4KR2 b - - 0 1").is_empty());
+        assert_eq!(violations("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), vec![Violation::OpponentInCheck]);
+    }
+}
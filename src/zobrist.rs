@@ -0,0 +1,43 @@
+use crate::types::{CastlingRights, Color, File, PieceType, Square};
+
+const PIECE_KEYS_OFFSET: usize = 0;
+const CASTLING_KEYS_OFFSET: usize = PIECE_KEYS_OFFSET + 2 * 6 * 64;
+const ENPASSANT_KEYS_OFFSET: usize = CASTLING_KEYS_OFFSET + 16;
+const SIDE_KEY_OFFSET: usize = ENPASSANT_KEYS_OFFSET + 8;
+const KEY_COUNT: usize = SIDE_KEY_OFFSET + 1;
+
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// xorshift64* keys, generated at compile time so every build hashes identically
+const KEYS: [u64; KEY_COUNT] = generate_keys();
+
+const fn generate_keys() -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = SEED;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        keys[i] = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        i += 1;
+    }
+    keys
+}
+
+// Keys describe the real board, squares and colors seen from white's side
+pub fn piece_key(sq: &Square, pt: &PieceType, color: &Color) -> u64 {
+    KEYS[PIECE_KEYS_OFFSET + ((color.0 as usize * 6 + pt.0 as usize) << 6) + sq.0 as usize]
+}
+
+pub fn castling_key(cr: &CastlingRights) -> u64 {
+    KEYS[CASTLING_KEYS_OFFSET + cr.0 as usize]
+}
+
+pub fn enpassant_key(file: &File) -> u64 {
+    KEYS[ENPASSANT_KEYS_OFFSET + file.0 as usize]
+}
+
+pub fn side_key() -> u64 {
+    KEYS[SIDE_KEY_OFFSET]
+}